@@ -0,0 +1,16 @@
+/* The tables exactly as the original reset_db.sql created them, before migrations existed, hence
+IF NOT EXISTS. Everything added since comes with the following migrations */
+CREATE TABLE IF NOT EXISTS boards (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS shapes (
+    id SERIAL PRIMARY KEY,
+    board_id INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    shape TEXT NOT NULL,
+    CONSTRAINT fk_board
+        FOREIGN KEY(board_id) REFERENCES boards(id)
+);
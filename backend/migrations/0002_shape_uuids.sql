@@ -0,0 +1,6 @@
+/* Shapes are identified by a UUID generated by the client that draws them, older shapes get a
+random one. IF NOT EXISTS as databases created by reset_db.sql may already have the column */
+ALTER TABLE shapes ADD COLUMN IF NOT EXISTS uuid UUID;
+UPDATE shapes SET uuid = gen_random_uuid() WHERE uuid IS NULL;
+ALTER TABLE shapes ALTER COLUMN uuid SET NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS shapes_uuid_key ON shapes (uuid);
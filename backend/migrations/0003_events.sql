@@ -0,0 +1,14 @@
+/* Operations on a board are recorded with increasing sequence numbers, see append_event. IF NOT
+EXISTS as databases created by reset_db.sql may already have them */
+ALTER TABLE boards ADD COLUMN IF NOT EXISTS last_seq BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS events (
+    id SERIAL PRIMARY KEY,
+    board_id INT NOT NULL,
+    seq BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    message TEXT NOT NULL,
+    UNIQUE (board_id, seq),
+    CONSTRAINT fk_board
+        FOREIGN KEY(board_id) REFERENCES boards(id)
+);
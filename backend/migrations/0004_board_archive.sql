@@ -0,0 +1,12 @@
+/* Deleted boards are archived first, and can be restored until they are deleted for good */
+ALTER TABLE boards ADD COLUMN archived_at TIMESTAMP;
+
+ALTER TABLE shapes
+    DROP CONSTRAINT fk_board,
+    ADD CONSTRAINT fk_board
+        FOREIGN KEY(board_id) REFERENCES boards(id) ON DELETE CASCADE;
+
+ALTER TABLE events
+    DROP CONSTRAINT fk_board,
+    ADD CONSTRAINT fk_board
+        FOREIGN KEY(board_id) REFERENCES boards(id) ON DELETE CASCADE;
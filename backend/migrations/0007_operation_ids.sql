@@ -0,0 +1,5 @@
+/* Ids given by clients to their shape operations, so that an operation sent again after a
+reconnection is only applied once */
+ALTER TABLE events ADD COLUMN op_id UUID;
+
+CREATE UNIQUE INDEX events_op_id ON events (board_id, op_id);
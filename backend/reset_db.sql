@@ -13,17 +13,4 @@ DROP DATABASE liveboard;
 CREATE DATABASE liveboard OWNER postgres;
 \c liveboard;
 
-CREATE TABLE boards (
-    id SERIAL PRIMARY KEY,
-    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
-    name TEXT NOT NULL
-);
-
-CREATE TABLE shapes (
-    id SERIAL PRIMARY KEY,
-    board_id INT NOT NULL,
-    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
-    shape TEXT NOT NULL,
-    CONSTRAINT fk_board
-        FOREIGN KEY(board_id) REFERENCES boards(id)
-);
\ No newline at end of file
+/* Tables are created by the backend's migrations, see `cargo run -- migrate` */
\ No newline at end of file
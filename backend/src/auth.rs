@@ -0,0 +1,162 @@
+// Accounts and sessions. Users sign in with a password and are then recognized by a random token
+// kept in a cookie, which also authenticates their websockets
+use crate::db::{models::User, MyError, State};
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::{dev, get, post, web, FromRequest, HttpRequest, HttpResponse};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{Duration, Utc};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use shared::datatypes as data;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+pub const SESSION_COOKIE: &str = "liveboard_session";
+const SESSION_DAYS: i64 = 30;
+
+pub fn hash_password(password: &str) -> Result<String, MyError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| MyError::InternalError(e.to_string()))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(e) => {
+            log::error!("Invalid password hash: {}", e);
+            false
+        }
+    }
+}
+
+// Checked against when the user doesn't exist, so that logging in takes as long and nobody can
+// tell which usernames are taken. Hashed once, with the same parameters as real ones
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password(&new_token()).unwrap())
+}
+
+// Random, long enough not to be guessed
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::days(SESSION_DAYS))
+        .finish()
+}
+
+// Starts a session for `user`, and sends its cookie
+async fn sign_in(db_state: &State, user: User) -> Result<HttpResponse, MyError> {
+    let token = new_token();
+    let expires_at = Utc::now().naive_utc() + Duration::days(SESSION_DAYS);
+    db_state
+        .store
+        .create_session(token.clone(), user.id, expires_at)
+        .await?;
+    Ok(HttpResponse::Ok()
+        .cookie(session_cookie(token))
+        .json(data::User::from(user)))
+}
+
+// Hashing is slow on purpose, so it is kept off the async workers
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, MyError> {
+    web::block(f)
+        .await
+        .map_err(|e| MyError::InternalError(e.to_string()))
+}
+
+// The signed in user. Unauthorized without a valid session
+pub struct CurrentUser(pub User);
+
+impl FromRequest for CurrentUser {
+    type Error = MyError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
+        let db_state = req.app_data::<web::Data<State>>().cloned();
+        let token = req.cookie(SESSION_COOKIE).map(|c| c.value().to_string());
+        Box::pin(async move {
+            let db_state = db_state
+                .ok_or_else(|| MyError::InternalError("Missing database state".to_string()))?;
+            let token = token.ok_or_else(not_signed_in)?;
+            match db_state.store.get_session_user(&token).await {
+                Ok(user) => Ok(CurrentUser(user)),
+                Err(MyError::NotFound) => Err(not_signed_in()),
+                Err(e) => Err(e),
+            }
+        })
+    }
+}
+
+fn not_signed_in() -> MyError {
+    MyError::Unauthorized("Sign in first".to_string())
+}
+
+#[post("/auth/register")]
+pub async fn register(
+    db_state: web::Data<State>,
+    credentials: web::Json<data::Credentials>,
+) -> Result<HttpResponse, MyError> {
+    credentials.validate().map_err(MyError::BadRequest)?;
+    let data::Credentials { username, password } = credentials.into_inner();
+    let hash = blocking(move || hash_password(&password)).await??;
+    let user = db_state.store.create_user(username, hash).await?;
+    db_state.store.adopt_ownerless_boards(user.id).await?;
+    log::info!("New user {}", user.username);
+    sign_in(&db_state, user).await
+}
+
+#[post("/auth/login")]
+pub async fn login(
+    db_state: web::Data<State>,
+    credentials: web::Json<data::Credentials>,
+) -> Result<HttpResponse, MyError> {
+    let data::Credentials { username, password } = credentials.into_inner();
+    // Same answer whether the user exists or not
+    let user = match db_state.store.get_user_by_name(&username).await {
+        Ok(user) => Some(user),
+        Err(MyError::NotFound) => None,
+        Err(e) => return Err(e),
+    };
+    let hash = user.as_ref().map(|user| user.password_hash.clone());
+    let valid = blocking(move || match &hash {
+        Some(hash) => verify_password(&password, hash),
+        None => verify_password(&password, dummy_hash()),
+    })
+    .await?;
+    match user.filter(|_| valid) {
+        Some(user) => sign_in(&db_state, user).await,
+        None => Err(MyError::Unauthorized(
+            "Wrong username or password".to_string(),
+        )),
+    }
+}
+
+#[post("/auth/logout")]
+pub async fn logout(db_state: web::Data<State>, req: HttpRequest) -> Result<HttpResponse, MyError> {
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        db_state.store.delete_session(cookie.value()).await?;
+    }
+    let mut removal = session_cookie(String::new());
+    removal.make_removal();
+    Ok(HttpResponse::NoContent().cookie(removal).finish())
+}
+
+#[get("/auth/me")]
+pub async fn me(user: CurrentUser) -> web::Json<data::User> {
+    web::Json(user.0.into())
+}
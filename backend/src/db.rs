@@ -1,13 +1,24 @@
 mod errors {
+    use actix_web::http::StatusCode;
     use actix_web::{HttpResponse, ResponseError};
     use deadpool_postgres::PoolError;
     use derive_more::{Display, From};
+    use shared::datatypes::ErrorResponse;
     use tokio_pg_mapper::Error as PGMError;
     use tokio_postgres::error::Error as PGError;
 
     #[derive(Display, From, Debug)]
     pub enum MyError {
         NotFound,
+        // Invalid input, the message is shown to the user
+        #[from(ignore)]
+        BadRequest(String),
+        // Not signed in, or wrong credentials
+        #[from(ignore)]
+        Unauthorized(String),
+        // Signed in, but not allowed to
+        #[from(ignore)]
+        Forbidden(String),
         PGError(PGError),
         PGMError(PGMError),
         PoolError(PoolError),
@@ -21,71 +32,155 @@ mod errors {
     }
 
     impl ResponseError for MyError {
-        fn error_response(&self) -> HttpResponse {
+        fn status_code(&self) -> StatusCode {
             match *self {
-                MyError::NotFound => HttpResponse::NotFound().finish(),
+                MyError::NotFound => StatusCode::NOT_FOUND,
+                MyError::BadRequest(_) => StatusCode::BAD_REQUEST,
+                MyError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+                MyError::Forbidden(_) => StatusCode::FORBIDDEN,
+                // No connection available in time, or the database is down
+                MyError::PoolError(_) => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        }
+
+        fn error_response(&self) -> HttpResponse {
+            // Details of server errors are logged, not sent
+            let (code, message) = match *self {
+                MyError::NotFound => ("not_found", "Not found".to_string()),
+                MyError::BadRequest(ref message) => ("bad_request", message.clone()),
+                MyError::Unauthorized(ref message) => ("unauthorized", message.clone()),
+                MyError::Forbidden(ref message) => ("forbidden", message.clone()),
                 MyError::PoolError(ref err) => {
-                    HttpResponse::InternalServerError().body(err.to_string())
+                    log::error!("Database unavailable: {}", err);
+                    (
+                        "unavailable",
+                        "Database unavailable, try again later".to_string(),
+                    )
                 }
-                _ => HttpResponse::InternalServerError().finish(),
-            }
+                _ => {
+                    log::error!("Internal error: {}", self);
+                    ("internal", "Internal error".to_string())
+                }
+            };
+            HttpResponse::build(self.status_code()).json(ErrorResponse {
+                code: code.to_string(),
+                message,
+            })
         }
     }
 }
 
 pub mod models {
+    use super::MyError;
     use chrono::{NaiveDateTime, Utc};
     use serde::{Deserialize, Serialize};
     use serde_json;
     use shared::datatypes as data;
     use tokio_pg_mapper_derive::PostgresMapper;
+    use tokio_postgres::types::ToSql;
+
+    pub type SqlParam<'a> = &'a (dyn ToSql + Sync);
 
     pub trait Insertable {
-        // Transform to (column name, value) tuples for INSERT statement
-        fn to_insert_tuples(&self) -> Vec<[String; 2]>;
+        // Transform to (column name, value) tuples for INSERT statement. Values are sent as bind
+        // parameters, never spliced into the SQL text
+        fn to_insert_tuples(&self) -> Vec<(&'static str, SqlParam<'_>)>;
     }
 
-    #[derive(Deserialize, PostgresMapper, Serialize, Debug)]
+    #[derive(Clone, Deserialize, PostgresMapper, Serialize, Debug)]
     #[pg_mapper(table = "shapes")]
     pub struct Shape {
         pub id: i32,
+        pub uuid: data::Uuid,
         pub board_id: i32,
         pub created_at: NaiveDateTime,
         pub shape: String,
     }
 
-    impl From<data::Shape> for Shape {
-        fn from(shape: data::Shape) -> Self {
+    impl From<data::ShapeEntry> for Shape {
+        fn from(entry: data::ShapeEntry) -> Self {
             Shape {
                 id: 0,
+                uuid: entry.id,
                 board_id: 0,
                 created_at: Utc::now().naive_utc(),
-                shape: serde_json::to_string(&shape).unwrap(),
+                shape: serde_json::to_string(&entry.shape).unwrap(),
             }
         }
     }
 
-    impl From<Shape> for data::Shape {
-        fn from(shape: Shape) -> Self {
-            serde_json::from_str::<data::Shape>(&shape.shape).unwrap()
+    // Fails on rows that don't hold a shape this version knows, rather than panicking
+    impl TryFrom<Shape> for data::ShapeEntry {
+        type Error = MyError;
+
+        fn try_from(shape: Shape) -> Result<Self, MyError> {
+            let parsed = serde_json::from_str::<data::Shape>(&shape.shape).map_err(|e| {
+                MyError::InternalError(format!("Invalid shape {}: {}", shape.uuid, e))
+            })?;
+            Ok(data::ShapeEntry {
+                id: shape.uuid,
+                shape: parsed,
+            })
         }
     }
 
     impl Insertable for Shape {
-        fn to_insert_tuples(&self) -> Vec<[String; 2]> {
-            return vec![
-                ["board_id".to_owned(), format!("{:?}", self.board_id)],
-                ["shape".to_owned(), self.shape.clone()],
-            ];
+        fn to_insert_tuples(&self) -> Vec<(&'static str, SqlParam<'_>)> {
+            vec![
+                ("uuid", &self.uuid),
+                ("board_id", &self.board_id),
+                ("shape", &self.shape),
+            ]
+        }
+    }
+
+    // Computed from the shapes and events of a board
+    #[derive(Clone, Debug, Default)]
+    pub struct BoardStats {
+        pub shape_count: i64,
+        pub event_count: i64,
+        pub last_event_at: Option<NaiveDateTime>,
+    }
+
+    // A persisted operation on a board, see data::BoardEvent
+    #[derive(Clone, Deserialize, PostgresMapper, Serialize, Debug)]
+    #[pg_mapper(table = "events")]
+    pub struct Event {
+        pub id: i32,
+        pub board_id: i32,
+        pub seq: i64,
+        pub created_at: NaiveDateTime,
+        pub message: String,
+        // Of the operation, if its client gave one, see data::Operation
+        pub op_id: Option<data::OperationId>,
+    }
+
+    impl TryFrom<Event> for data::BoardEvent {
+        type Error = MyError;
+
+        fn try_from(event: Event) -> Result<Self, MyError> {
+            let message =
+                serde_json::from_str::<data::SocketMessage>(&event.message).map_err(|e| {
+                    MyError::InternalError(format!("Invalid event {}: {}", event.id, e))
+                })?;
+            Ok(data::BoardEvent {
+                seq: event.seq,
+                message,
+            })
         }
     }
 
-    #[derive(Deserialize, PostgresMapper, Serialize, Debug)]
+    #[derive(Clone, Deserialize, PostgresMapper, Serialize, Debug)]
     #[pg_mapper(table = "boards")]
     pub struct Board {
         pub id: i32,
         pub created_at: NaiveDateTime,
         pub name: String,
+        // Set when the board is deleted, until it is restored or deleted for good
+        pub archived_at: Option<NaiveDateTime>,
+        // Sequence number of its last event, see apply_and_record
+        pub last_seq: i64,
     }
 
     impl Board {
@@ -94,6 +189,8 @@ pub mod models {
                 id: 0,
                 created_at: Utc::now().naive_utc(),
                 name,
+                archived_at: None,
+                last_seq: 0,
             }
         }
     }
@@ -103,78 +200,744 @@ pub mod models {
             data::Board {
                 name: board.name,
                 id: board.id,
+                archived: board.archived_at.is_some(),
+                created_at: board.created_at,
             }
         }
     }
 
     impl Insertable for Board {
-        fn to_insert_tuples(&self) -> Vec<[String; 2]> {
-            return vec![["name".to_owned(), self.name.clone()]];
+        fn to_insert_tuples(&self) -> Vec<(&'static str, SqlParam<'_>)> {
+            vec![("name", &self.name)]
+        }
+    }
+
+    #[derive(Clone, Deserialize, PostgresMapper, Serialize, Debug)]
+    #[pg_mapper(table = "users")]
+    pub struct User {
+        pub id: i32,
+        pub created_at: NaiveDateTime,
+        pub username: String,
+        // PHC string, see auth::hash_password
+        pub password_hash: String,
+    }
+
+    impl User {
+        pub fn new(username: String, password_hash: String) -> User {
+            User {
+                id: 0,
+                created_at: Utc::now().naive_utc(),
+                username,
+                password_hash,
+            }
+        }
+    }
+
+    impl From<User> for data::User {
+        fn from(user: User) -> Self {
+            data::User {
+                id: user.id,
+                username: user.username,
+            }
+        }
+    }
+
+    impl Insertable for User {
+        fn to_insert_tuples(&self) -> Vec<(&'static str, SqlParam<'_>)> {
+            vec![
+                ("username", &self.username),
+                ("password_hash", &self.password_hash),
+            ]
+        }
+    }
+
+    #[derive(Clone, Deserialize, PostgresMapper, Serialize, Debug)]
+    #[pg_mapper(table = "sessions")]
+    pub struct Session {
+        pub id: i32,
+        // Sent in the session cookie
+        pub token: String,
+        pub user_id: i32,
+        pub created_at: NaiveDateTime,
+        pub expires_at: NaiveDateTime,
+    }
+
+    impl Insertable for Session {
+        fn to_insert_tuples(&self) -> Vec<(&'static str, SqlParam<'_>)> {
+            vec![
+                ("token", &self.token),
+                ("user_id", &self.user_id),
+                ("expires_at", &self.expires_at),
+            ]
+        }
+    }
+
+    #[derive(Clone, Deserialize, PostgresMapper, Serialize, Debug)]
+    #[pg_mapper(table = "invites")]
+    pub struct Invite {
+        pub id: i32,
+        pub board_id: i32,
+        pub token: String,
+        // viewer or editor
+        pub role: String,
+        pub created_at: NaiveDateTime,
+        pub expires_at: Option<NaiveDateTime>,
+    }
+
+    impl Invite {
+        pub fn new(
+            board_id: i32,
+            token: String,
+            role: data::Role,
+            expires_at: Option<NaiveDateTime>,
+        ) -> Invite {
+            Invite {
+                id: 0,
+                board_id,
+                token,
+                role: role.as_str().to_string(),
+                created_at: Utc::now().naive_utc(),
+                expires_at,
+            }
+        }
+
+        // The table only allows valid roles, the fallback grants the least
+        pub fn role(&self) -> data::Role {
+            data::Role::parse(&self.role).unwrap_or(data::Role::Viewer)
+        }
+
+        pub fn is_expired(&self) -> bool {
+            self.expires_at
+                .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+        }
+    }
+
+    impl From<Invite> for data::Invite {
+        fn from(invite: Invite) -> Self {
+            data::Invite {
+                id: invite.id,
+                role: invite.role(),
+                token: invite.token,
+                created_at: invite.created_at,
+                expires_at: invite.expires_at,
+            }
+        }
+    }
+
+    impl Insertable for Invite {
+        fn to_insert_tuples(&self) -> Vec<(&'static str, SqlParam<'_>)> {
+            vec![
+                ("board_id", &self.board_id),
+                ("token", &self.token),
+                ("role", &self.role),
+                ("expires_at", &self.expires_at),
+            ]
+        }
+    }
+
+    // A row of board_members, with the username
+    #[derive(Clone, Debug)]
+    pub struct Member {
+        pub user_id: i32,
+        pub username: String,
+        pub role: data::Role,
+    }
+
+    impl From<Member> for data::Member {
+        fn from(member: Member) -> Self {
+            data::Member {
+                username: member.username,
+                role: member.role,
+            }
         }
     }
 }
 
-pub use deadpool_postgres::Client;
-use deadpool_postgres::{Config, Pool};
-use errors::MyError;
-use models::{Board, Shape};
+pub mod memory;
+mod migrations;
+
+use crate::config::{Config, ConfigError, StoreKind};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Manager;
+pub use deadpool_postgres::{Client, Pool};
+pub use errors::MyError;
+use models::{Board, BoardStats, Event, Invite, Member, Session, Shape, User};
 use shared::datatypes as data;
-use std::env;
-use std::fmt::Write as _;
+use std::sync::Arc;
 use tokio_pg_mapper::FromTokioPostgresRow;
 use tokio_postgres::NoTls;
 
-use self::models::Insertable;
+use self::models::{Insertable, SqlParam};
+
+// Id of the `index`th shape of an imported board. Shapes get new ids so that an archive can be
+// imported many times. These are version 8 UUIDs, which never collide with the random version 4
+// ones clients generate
+pub fn imported_shape_id(board_id: i32, index: usize) -> data::Uuid {
+    let mut bytes = [0u8; 16];
+    bytes[..4].copy_from_slice(&board_id.to_be_bytes());
+    bytes[8..].copy_from_slice(&(index as u64).to_be_bytes());
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+// Which boards BoardStore::get_boards returns, and in which order
+#[derive(Clone, Debug)]
+pub struct BoardQuery {
+    // Only boards this user is a member of
+    pub user_id: i32,
+    pub archived: bool,
+    // Only boards whose name contains it, ignoring case
+    pub search: Option<String>,
+    // Names are sorted ignoring case
+    pub sort: data::BoardSort,
+    // Only boards that come after this one in `sort` order
+    pub after: Option<BoardCursor>,
+    pub limit: i64,
+}
+
+// Sort keys of the last board of a page, which the next page starts after
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardCursor {
+    pub id: i32,
+    pub name: String,
+}
+
+impl BoardCursor {
+    // Clients shouldn't rely on what's inside
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}:{}", self.id, self.name))
+    }
+
+    pub fn decode(cursor: &str) -> Result<BoardCursor, MyError> {
+        let invalid = || MyError::BadRequest("Invalid cursor".to_string());
+        let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+        let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (id, name) = text.split_once(':').ok_or_else(invalid)?;
+        Ok(BoardCursor {
+            id: id.parse().map_err(|_| invalid())?,
+            name: name.to_string(),
+        })
+    }
+}
+
+impl From<&Board> for BoardCursor {
+    fn from(board: &Board) -> Self {
+        BoardCursor {
+            id: board.id,
+            name: board.name.clone(),
+        }
+    }
+}
+
+// Everything the handlers need to persist boards, their shapes and their events, and who can
+// access them
+#[async_trait]
+pub trait BoardStore: Send + Sync {
+    // Brings the storage schema up to date. Called once at startup
+    async fn migrate(&self) -> Result<(), MyError>;
+    async fn get_boards(&self, query: &BoardQuery) -> Result<Vec<Board>, MyError>;
+    // NotFound if there is no such board, archived boards included
+    async fn get_board(&self, board_id: i32) -> Result<Board, MyError>;
+    async fn create_board(&self, name: String, owner_id: i32) -> Result<Board, MyError>;
+    // Creates a board with the given shapes, in order, all at once. See imported_shape_id
+    async fn import_board(
+        &self,
+        name: String,
+        owner_id: i32,
+        shapes: Vec<data::Shape>,
+    ) -> Result<Board, MyError>;
+    async fn rename_board(&self, board_id: i32, name: String) -> Result<Board, MyError>;
+    async fn set_board_archived(&self, board_id: i32, archived: bool) -> Result<Board, MyError>;
+    // For good, with its shapes and events
+    async fn delete_board(&self, board_id: i32) -> Result<(), MyError>;
+    async fn get_board_stats(&self, board_id: i32) -> Result<BoardStats, MyError>;
+    async fn get_shapes(&self, board_id: i32) -> Result<Vec<Shape>, MyError>;
+    // Applies the shape operation and records it as the next event of the board, see
+    // data::BoardEvent, both or neither. None if it changed nothing: `op_id` was already
+    // recorded on the board, or the shape it creates already exists. NotFound if the shape to
+    // update or delete doesn't exist
+    async fn apply_and_record(
+        &self,
+        board_id: i32,
+        op_id: Option<data::OperationId>,
+        message: &data::SocketMessage,
+    ) -> Result<Option<Event>, MyError>;
+    async fn get_events(&self, board_id: i32, since: i64) -> Result<Vec<Event>, MyError>;
+    // BadRequest if the username is taken
+    async fn create_user(&self, username: String, password_hash: String) -> Result<User, MyError>;
+    async fn get_user_by_name(&self, username: &str) -> Result<User, MyError>;
+    // Makes `user_id` the owner of the boards that have none, which were created before there
+    // were accounts. Called on every registration, so the first user gets them all
+    async fn adopt_ownerless_boards(&self, user_id: i32) -> Result<(), MyError>;
+    async fn create_session(
+        &self,
+        token: String,
+        user_id: i32,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), MyError>;
+    // NotFound if there is no such session, or if it expired
+    async fn get_session_user(&self, token: &str) -> Result<User, MyError>;
+    async fn delete_session(&self, token: &str) -> Result<(), MyError>;
+    // Owner first
+    async fn get_members(&self, board_id: i32) -> Result<Vec<Member>, MyError>;
+    // Adds the member, or changes their role
+    async fn set_member(
+        &self,
+        board_id: i32,
+        user_id: i32,
+        role: data::Role,
+    ) -> Result<(), MyError>;
+    async fn remove_member(&self, board_id: i32, user_id: i32) -> Result<(), MyError>;
+    async fn create_invite(&self, invite: Invite) -> Result<Invite, MyError>;
+    // Oldest first, including the expired ones
+    async fn get_invites(&self, board_id: i32) -> Result<Vec<Invite>, MyError>;
+    // NotFound if there is no such invite, expired or not
+    async fn get_invite(&self, token: &str) -> Result<Invite, MyError>;
+    async fn delete_invite(&self, board_id: i32, invite_id: i32) -> Result<(), MyError>;
+}
 
 pub struct State {
-    pub pool: Pool,
+    pub store: Arc<dyn BoardStore>,
 }
 
-pub fn make_state() -> State {
-    let mut cfg = Config::new();
-    cfg.user = Some("postgres".to_string());
-    cfg.password = Some(match env::var("DB_PASSWORD") {
-        Ok(val) => val,
-        Err(_) => "postgres".to_string(),
-    });
-    cfg.host = Some("localhost".to_string());
-    cfg.dbname = Some("liveboard".to_string());
-    let pool = cfg.create_pool(None, NoTls).unwrap();
+impl State {
+    pub fn new(store: impl BoardStore + 'static) -> State {
+        State {
+            store: Arc::new(store),
+        }
+    }
+
+    // Archived boards can only be listed, restored or deleted, so they are NotFound otherwise
+    pub async fn get_active_board(&self, board_id: i32) -> Result<Board, MyError> {
+        let board = self.store.get_board(board_id).await?;
+        match board.archived_at {
+            Some(_) => Err(MyError::NotFound),
+            None => Ok(board),
+        }
+    }
+
+    // The role of `user` on `board`. NotFound if they aren't a member, so that boards can't be
+    // discovered by trying ids, and Forbidden if their role is below `min_role`
+    pub async fn check_role(
+        &self,
+        board: &Board,
+        user: &User,
+        min_role: data::Role,
+    ) -> Result<data::Role, MyError> {
+        self.check_access(board, Some(user), None, min_role).await
+    }
+
+    // Like check_role, for someone who may not be signed in but may have an invite token to
+    // the board. The best of their two roles applies. Unauthorized if they have neither
+    pub async fn check_access(
+        &self,
+        board: &Board,
+        user: Option<&User>,
+        invite: Option<&str>,
+        min_role: data::Role,
+    ) -> Result<data::Role, MyError> {
+        let mut role = None;
+        if let Some(user) = user {
+            role = self.member_role(board, user).await?;
+        }
+        if let Some(token) = invite {
+            match self.get_valid_invite(token).await {
+                Ok(invite) if invite.board_id == board.id => role = role.max(Some(invite.role())),
+                Ok(_) | Err(MyError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let role = match role {
+            Some(role) => role,
+            None if user.is_none() => {
+                return Err(MyError::Unauthorized("Sign in first".to_string()))
+            }
+            None => return Err(MyError::NotFound),
+        };
+        if role < min_role {
+            return Err(MyError::Forbidden(format!(
+                "This needs the {} role on the board",
+                min_role.as_str()
+            )));
+        }
+        Ok(role)
+    }
+
+    // None if `user` isn't a member of `board`
+    pub async fn member_role(
+        &self,
+        board: &Board,
+        user: &User,
+    ) -> Result<Option<data::Role>, MyError> {
+        let members = self.store.get_members(board.id).await?;
+        Ok(members
+            .iter()
+            .find(|m| m.user_id == user.id)
+            .map(|m| m.role))
+    }
+
+    // NotFound once expired
+    pub async fn get_valid_invite(&self, token: &str) -> Result<Invite, MyError> {
+        let invite = self.store.get_invite(token).await?;
+        match invite.is_expired() {
+            true => Err(MyError::NotFound),
+            false => Ok(invite),
+        }
+    }
+}
+
+pub fn make_state(config: &Config) -> Result<State, ConfigError> {
+    match config.store {
+        StoreKind::Memory => {
+            log::warn!("Using the in-memory store, boards will be lost on restart");
+            Ok(State::new(memory::MemoryStore::new()))
+        }
+        StoreKind::Postgres => Ok(State::new(PgStore::new(make_pool(config)?))),
+    }
+}
+
+// Connections are only opened when needed, so this doesn't check the database is reachable
+fn make_pool(config: &Config) -> Result<Pool, ConfigError> {
+    let manager = Manager::new(config.database_url.clone(), NoTls);
+    Pool::builder(manager)
+        .max_size(config.pool_size)
+        .build()
+        .map_err(|e| ConfigError::Invalid("pool size", config.pool_size.to_string(), e.to_string()))
+}
+
+pub struct PgStore {
+    pool: Pool,
+}
+
+impl PgStore {
+    pub fn new(pool: Pool) -> PgStore {
+        PgStore { pool }
+    }
+}
+
+#[async_trait]
+impl BoardStore for PgStore {
+    async fn migrate(&self) -> Result<(), MyError> {
+        let applied = migrations::run(&mut self.pool.get().await?).await?;
+        log::info!("Applied {} migrations", applied.len());
+        Ok(())
+    }
+
+    async fn get_boards(&self, query: &BoardQuery) -> Result<Vec<Board>, MyError> {
+        get_boards(&self.pool.get().await?, query).await
+    }
+
+    async fn get_board(&self, board_id: i32) -> Result<Board, MyError> {
+        get_by_id(&self.pool.get().await?, board_id).await
+    }
+
+    async fn create_board(&self, name: String, owner_id: i32) -> Result<Board, MyError> {
+        create_board(&self.pool.get().await?, name, owner_id).await
+    }
+
+    async fn import_board(
+        &self,
+        name: String,
+        owner_id: i32,
+        shapes: Vec<data::Shape>,
+    ) -> Result<Board, MyError> {
+        import_board(&mut self.pool.get().await?, name, owner_id, shapes).await
+    }
+
+    async fn rename_board(&self, board_id: i32, name: String) -> Result<Board, MyError> {
+        rename_board(&self.pool.get().await?, board_id, name).await
+    }
+
+    async fn set_board_archived(&self, board_id: i32, archived: bool) -> Result<Board, MyError> {
+        set_board_archived(&self.pool.get().await?, board_id, archived).await
+    }
+
+    async fn delete_board(&self, board_id: i32) -> Result<(), MyError> {
+        delete_board(&self.pool.get().await?, board_id).await
+    }
+
+    async fn get_board_stats(&self, board_id: i32) -> Result<BoardStats, MyError> {
+        get_board_stats(&self.pool.get().await?, board_id).await
+    }
+
+    async fn get_shapes(&self, board_id: i32) -> Result<Vec<Shape>, MyError> {
+        get_shapes(&self.pool.get().await?, board_id).await
+    }
+
+    async fn apply_and_record(
+        &self,
+        board_id: i32,
+        op_id: Option<data::OperationId>,
+        message: &data::SocketMessage,
+    ) -> Result<Option<Event>, MyError> {
+        apply_and_record(&mut self.pool.get().await?, board_id, op_id, message).await
+    }
+
+    async fn get_events(&self, board_id: i32, since: i64) -> Result<Vec<Event>, MyError> {
+        get_events(&self.pool.get().await?, board_id, since).await
+    }
+
+    async fn create_user(&self, username: String, password_hash: String) -> Result<User, MyError> {
+        create_user(&self.pool.get().await?, username, password_hash).await
+    }
+
+    async fn get_user_by_name(&self, username: &str) -> Result<User, MyError> {
+        get_user_by_name(&self.pool.get().await?, username).await
+    }
+
+    async fn adopt_ownerless_boards(&self, user_id: i32) -> Result<(), MyError> {
+        adopt_ownerless_boards(&self.pool.get().await?, user_id).await
+    }
+
+    async fn create_session(
+        &self,
+        token: String,
+        user_id: i32,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), MyError> {
+        create_session(&self.pool.get().await?, token, user_id, expires_at).await
+    }
+
+    async fn get_session_user(&self, token: &str) -> Result<User, MyError> {
+        get_session_user(&self.pool.get().await?, token).await
+    }
+
+    async fn delete_session(&self, token: &str) -> Result<(), MyError> {
+        delete_session(&self.pool.get().await?, token).await
+    }
+
+    async fn get_members(&self, board_id: i32) -> Result<Vec<Member>, MyError> {
+        get_members(&self.pool.get().await?, board_id).await
+    }
+
+    async fn set_member(
+        &self,
+        board_id: i32,
+        user_id: i32,
+        role: data::Role,
+    ) -> Result<(), MyError> {
+        set_member(&self.pool.get().await?, board_id, user_id, role).await
+    }
+
+    async fn remove_member(&self, board_id: i32, user_id: i32) -> Result<(), MyError> {
+        remove_member(&self.pool.get().await?, board_id, user_id).await
+    }
+
+    async fn create_invite(&self, invite: Invite) -> Result<Invite, MyError> {
+        insert(&self.pool.get().await?, &invite).await
+    }
+
+    async fn get_invites(&self, board_id: i32) -> Result<Vec<Invite>, MyError> {
+        let filter = Filter::new().eq("board_id", &board_id).order_by("id");
+        list(&self.pool.get().await?, filter).await
+    }
+
+    async fn get_invite(&self, token: &str) -> Result<Invite, MyError> {
+        let filter = Filter::new().eq("token", &token);
+        let invites: Vec<Invite> = list(&self.pool.get().await?, filter).await?;
+        invites.into_iter().next().ok_or(MyError::NotFound)
+    }
+
+    async fn delete_invite(&self, board_id: i32, invite_id: i32) -> Result<(), MyError> {
+        let filter = Filter::new().eq("id", &invite_id).eq("board_id", &board_id);
+        match delete::<Invite>(&self.pool.get().await?, filter).await? {
+            0 => Err(MyError::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Conditions on columns, combined with AND, and an optional ordering. Column names are static so
+// only values, which are passed as bind parameters, can come from user input
+#[derive(Default)]
+pub struct Filter<'a> {
+    conditions: Vec<Condition<'a>>,
+    order_by: Option<&'static str>,
+    limit: Option<i64>,
+}
+
+enum Condition<'a> {
+    // Column, operator and value
+    Compare(&'static str, &'static str, SqlParam<'a>),
+    // Columns, operator and values, compared as rows. Each column comes with the expression its
+    // value is put in, at `{}`
+    Row(
+        &'static [(&'static str, &'static str)],
+        &'static str,
+        Vec<SqlParam<'a>>,
+    ),
+    // Column, and SELECT statement with one `{}` parameter returning the values it may have
+    InSubquery(&'static str, &'static str, SqlParam<'a>),
+    // Column and whether it must be NULL
+    Null(&'static str, bool),
+}
+
+impl<'a> Filter<'a> {
+    pub fn new() -> Filter<'a> {
+        Filter::default()
+    }
+
+    pub fn eq(mut self, column: &'static str, value: SqlParam<'a>) -> Filter<'a> {
+        self.conditions.push(Condition::Compare(column, "=", value));
+        self
+    }
+
+    pub fn gt(mut self, column: &'static str, value: SqlParam<'a>) -> Filter<'a> {
+        self.conditions.push(Condition::Compare(column, ">", value));
+        self
+    }
+
+    pub fn lt(mut self, column: &'static str, value: SqlParam<'a>) -> Filter<'a> {
+        self.conditions.push(Condition::Compare(column, "<", value));
+        self
+    }
+
+    // `value` is a LIKE pattern, matched ignoring case
+    pub fn ilike(mut self, column: &'static str, value: SqlParam<'a>) -> Filter<'a> {
+        self.conditions
+            .push(Condition::Compare(column, " ILIKE ", value));
+        self
+    }
+
+    // Rows that sort after `values` when ordered by `columns`, for keyset pagination. Each column
+    // comes with the expression its value is put in, e.g. ("lower(name)", "lower({})"), so that
+    // both sides are computed the same way
+    pub fn row_gt(
+        mut self,
+        columns: &'static [(&'static str, &'static str)],
+        values: Vec<SqlParam<'a>>,
+    ) -> Filter<'a> {
+        self.conditions.push(Condition::Row(columns, ">", values));
+        self
+    }
+
+    pub fn in_subquery(
+        mut self,
+        column: &'static str,
+        subquery: &'static str,
+        value: SqlParam<'a>,
+    ) -> Filter<'a> {
+        self.conditions
+            .push(Condition::InSubquery(column, subquery, value));
+        self
+    }
+
+    pub fn is_null(mut self, column: &'static str, null: bool) -> Filter<'a> {
+        self.conditions.push(Condition::Null(column, null));
+        self
+    }
 
-    State { pool }
+    // As in an ORDER BY clause, e.g. "name, id" or "id DESC". Only used by SELECT statements
+    pub fn order_by(mut self, columns: &'static str) -> Filter<'a> {
+        self.order_by = Some(columns);
+        self
+    }
+
+    // Only used by SELECT statements
+    pub fn limit(mut self, limit: i64) -> Filter<'a> {
+        self.limit = Some(limit);
+        self
+    }
+
+    // Returns the WHERE clause (empty if there are no conditions) and its parameters, numbered
+    // from `$first_param`
+    fn to_sql(&self, first_param: usize) -> (String, Vec<SqlParam<'a>>) {
+        if self.conditions.is_empty() {
+            return (String::new(), vec![]);
+        }
+        let mut clauses: Vec<String> = vec![];
+        let mut params: Vec<SqlParam<'a>> = vec![];
+        for condition in self.conditions.iter() {
+            match condition {
+                Condition::Compare(column, op, value) => {
+                    params.push(*value);
+                    clauses.push(format!(
+                        "{}{}${}",
+                        column,
+                        op,
+                        first_param + params.len() - 1
+                    ));
+                }
+                Condition::Row(columns, op, values) => {
+                    let placeholders: Vec<String> = columns
+                        .iter()
+                        .zip(values)
+                        .map(|((_, expression), value)| {
+                            params.push(*value);
+                            let placeholder = format!("${}", first_param + params.len() - 1);
+                            expression.replace("{}", &placeholder)
+                        })
+                        .collect();
+                    let columns: Vec<&str> = columns.iter().map(|(column, _)| *column).collect();
+                    clauses.push(format!(
+                        "({}){}({})",
+                        columns.join(", "),
+                        op,
+                        placeholders.join(", ")
+                    ));
+                }
+                Condition::InSubquery(column, subquery, value) => {
+                    params.push(*value);
+                    let placeholder = format!("${}", first_param + params.len() - 1);
+                    clauses.push(format!(
+                        "{} IN ({})",
+                        column,
+                        subquery.replace("{}", &placeholder)
+                    ));
+                }
+                Condition::Null(column, true) => clauses.push(format!("{} IS NULL", column)),
+                Condition::Null(column, false) => clauses.push(format!("{} IS NOT NULL", column)),
+            }
+        }
+        (format!(" WHERE {}", clauses.join(" AND ")), params)
+    }
+
+    fn order_by_sql(&self) -> String {
+        let order_by = match self.order_by {
+            Some(columns) => format!(" ORDER BY {}", columns),
+            None => String::new(),
+        };
+        match self.limit {
+            Some(limit) => format!("{} LIMIT {}", order_by, limit),
+            None => order_by,
+        }
+    }
 }
 
 async fn get_by_id<T: FromTokioPostgresRow>(client: &Client, id: i32) -> Result<T, MyError> {
     let raw_stmt = format!(
-        "SELECT {} FROM {} WHERE id={} LIMIT 1;",
+        "SELECT {} FROM {} WHERE id=$1 LIMIT 1;",
         &T::sql_table_fields(),
         &T::sql_table(),
-        id
     );
-    let stmt = client.prepare(&raw_stmt).await.unwrap();
-    let row = match client.query_one(&stmt, &[]).await {
-        Ok(row) => row,
+    let stmt = client.prepare(&raw_stmt).await?;
+    let row = match client.query_opt(&stmt, &[&id]).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return Err(MyError::NotFound),
         Err(e) => {
             log::error!("Error executing {:?}: {:?}", &raw_stmt, e.to_string());
             return Err(MyError::PGError(e));
         }
     };
-    let t = T::from_row_ref(&row).unwrap();
+    let t = T::from_row_ref(&row)?;
     Ok(t)
 }
 
 async fn list<T: FromTokioPostgresRow>(
     client: &Client,
-    where_statement: Option<String>,
+    filter: Filter<'_>,
 ) -> Result<Vec<T>, MyError> {
-    let mut stmt = format!("SELECT {} FROM {}", &T::sql_table_fields(), &T::sql_table());
-    if where_statement.is_some() {
-        write!(stmt, " WHERE {}", where_statement.unwrap())?;
-    }
-    write!(stmt, ";")?;
-    let stmt = client.prepare(&stmt).await.unwrap();
+    let (where_clause, params) = filter.to_sql(1);
+    let raw_stmt = format!(
+        "SELECT {} FROM {}{}{};",
+        &T::sql_table_fields(),
+        &T::sql_table(),
+        where_clause,
+        filter.order_by_sql()
+    );
+    let stmt = client.prepare(&raw_stmt).await?;
     let r = client
-        .query(&stmt, &[])
+        .query(&stmt, &params)
         .await?
         .iter()
         .map(|row| T::from_row_ref(row))
@@ -189,26 +952,50 @@ async fn insert<T: Insertable + FromTokioPostgresRow>(
     client: &Client,
     t: &T,
 ) -> Result<T, MyError> {
-    let mut columns: Vec<String> = vec![];
-    let mut values: Vec<String> = vec![];
-    for [column_name, value] in t.to_insert_tuples() {
+    insert_with(client, t, "")
+        .await?
+        .ok_or_else(|| MyError::InternalError("insert returned no row".to_string()))
+}
+
+// Like insert, but returns None instead of failing if a row with the same `conflict_column`
+// value already exists
+async fn insert_if_absent<T: Insertable + FromTokioPostgresRow>(
+    client: &Client,
+    t: &T,
+    conflict_column: &str,
+) -> Result<Option<T>, MyError> {
+    let on_conflict = format!(" ON CONFLICT ({}) DO NOTHING", conflict_column);
+    insert_with(client, t, &on_conflict).await
+}
+
+async fn insert_with<T: Insertable + FromTokioPostgresRow>(
+    client: &Client,
+    t: &T,
+    on_conflict: &str,
+) -> Result<Option<T>, MyError> {
+    let mut columns: Vec<&str> = vec![];
+    let mut placeholders: Vec<String> = vec![];
+    let mut params: Vec<SqlParam> = vec![];
+    for (i, (column_name, value)) in t.to_insert_tuples().into_iter().enumerate() {
         columns.push(column_name);
-        values.push(value);
+        placeholders.push(format!("${}", i + 1));
+        params.push(value);
     }
     let raw_stmt = format!(
-        "INSERT INTO {} ({}) VALUES ({}) RETURNING id;",
+        "INSERT INTO {} ({}) VALUES ({}){} RETURNING id;",
         &T::sql_table(),
         columns.join(","),
-        values
-            .into_iter()
-            .map(|v| format!("'{}'", v))
-            .collect::<Vec<String>>()
-            .join(",")
+        placeholders.join(","),
+        on_conflict
     );
     log::debug!("statement: {:?}", raw_stmt);
-    let stmt = client.prepare(&raw_stmt).await.unwrap();
-    let row = match client.query_one(&stmt, &[]).await {
-        Ok(row) => row,
+    let stmt = client.prepare(&raw_stmt).await?;
+    let row = match client.query_opt(&stmt, &params).await {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            log::debug!("inserted 0");
+            return Ok(None);
+        }
         Err(e) => {
             log::error!("Error executing {:?}: {:?}", &raw_stmt, e.to_string());
             return Err(MyError::PGError(e));
@@ -218,31 +1005,437 @@ async fn insert<T: Insertable + FromTokioPostgresRow>(
     let t: T = get_by_id(client, id).await?;
     log::debug!("inserted 1");
 
-    Ok(t)
+    Ok(Some(t))
 }
 
-pub async fn get_boards(client: &Client) -> Result<Vec<Board>, MyError> {
-    list::<Board>(client, None).await
+// Overwrites the columns of `t` on the rows matching `filter`, returning the number of updated rows
+async fn update<T: Insertable + FromTokioPostgresRow>(
+    client: &Client,
+    t: &T,
+    filter: Filter<'_>,
+) -> Result<u64, MyError> {
+    let mut assignments: Vec<String> = vec![];
+    let mut params: Vec<SqlParam> = vec![];
+    for (i, (column_name, value)) in t.to_insert_tuples().into_iter().enumerate() {
+        assignments.push(format!("{}=${}", column_name, i + 1));
+        params.push(value);
+    }
+    let (where_clause, filter_params) = filter.to_sql(params.len() + 1);
+    if where_clause.is_empty() {
+        return Err(MyError::InternalError(
+            "Refusing to update without a filter".to_string(),
+        ));
+    }
+    params.extend(filter_params);
+    let raw_stmt = format!(
+        "UPDATE {} SET {}{};",
+        &T::sql_table(),
+        assignments.join(","),
+        where_clause
+    );
+    log::debug!("statement: {:?}", raw_stmt);
+    let stmt = client.prepare(&raw_stmt).await?;
+    Ok(client.execute(&stmt, &params).await?)
+}
+
+// Deletes the rows matching `filter`, returning the number of deleted rows
+async fn delete<T: FromTokioPostgresRow>(
+    client: &Client,
+    filter: Filter<'_>,
+) -> Result<u64, MyError> {
+    let (where_clause, params) = filter.to_sql(1);
+    if where_clause.is_empty() {
+        return Err(MyError::InternalError(
+            "Refusing to delete without a filter".to_string(),
+        ));
+    }
+    let raw_stmt = format!("DELETE FROM {}{};", &T::sql_table(), where_clause);
+    log::debug!("statement: {:?}", raw_stmt);
+    let stmt = client.prepare(&raw_stmt).await?;
+    Ok(client.execute(&stmt, &params).await?)
+}
+
+pub async fn get_boards(client: &Client, query: &BoardQuery) -> Result<Vec<Board>, MyError> {
+    // % and _ are wildcards in LIKE patterns, and a backslash escapes them
+    let pattern = query.search.as_ref().map(|search| {
+        let escaped = search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    let mut filter = Filter::new()
+        .in_subquery(
+            "id",
+            "SELECT board_id FROM board_members WHERE user_id={}",
+            &query.user_id,
+        )
+        .is_null("archived_at", !query.archived);
+    if let Some(pattern) = &pattern {
+        filter = filter.ilike("name", pattern);
+    }
+    if let Some(after) = &query.after {
+        filter = match query.sort {
+            data::BoardSort::Oldest => filter.gt("id", &after.id),
+            data::BoardSort::Newest => filter.lt("id", &after.id),
+            data::BoardSort::Name => filter.row_gt(
+                &[("lower(name)", "lower({})"), ("id", "{}")],
+                vec![&after.name, &after.id],
+            ),
+        };
+    }
+    let filter = match query.sort {
+        data::BoardSort::Oldest => filter.order_by("id"),
+        data::BoardSort::Newest => filter.order_by("id DESC"),
+        data::BoardSort::Name => filter.order_by("lower(name), id"),
+    };
+    list::<Board>(client, filter.limit(query.limit)).await
+}
+
+// A single statement, so there is never a board without owner
+pub async fn create_board(client: &Client, name: String, owner_id: i32) -> Result<Board, MyError> {
+    let stmt = client
+        .prepare(
+            "WITH board AS (INSERT INTO boards (name) VALUES ($1) RETURNING id) \
+            INSERT INTO board_members (board_id, user_id, role) \
+            SELECT id, $2, 'owner' FROM board RETURNING board_id;",
+        )
+        .await?;
+    let row = client.query_one(&stmt, &[&name, &owner_id]).await?;
+    get_by_id(client, row.get(0)).await
+}
+
+// In a transaction, so that a failed import leaves no half-filled board behind
+pub async fn import_board(
+    client: &mut Client,
+    name: String,
+    owner_id: i32,
+    shapes: Vec<data::Shape>,
+) -> Result<Board, MyError> {
+    let tx = client.transaction().await?;
+    let row = tx
+        .query_one(
+            "INSERT INTO boards (name) VALUES ($1) RETURNING id;",
+            &[&name],
+        )
+        .await?;
+    let board_id: i32 = row.get(0);
+    tx.execute(
+        "INSERT INTO board_members (board_id, user_id, role) VALUES ($1, $2, 'owner');",
+        &[&board_id, &owner_id],
+    )
+    .await?;
+    let stmt = tx
+        .prepare("INSERT INTO shapes (uuid, board_id, shape) VALUES ($1, $2, $3);")
+        .await?;
+    for (i, shape) in shapes.into_iter().enumerate() {
+        let shape: Shape = data::ShapeEntry {
+            id: imported_shape_id(board_id, i),
+            shape,
+        }
+        .into();
+        tx.execute(&stmt, &[&shape.uuid, &board_id, &shape.shape])
+            .await?;
+    }
+    tx.commit().await?;
+    get_by_id(client, board_id).await
+}
+
+pub async fn rename_board(client: &Client, board_id: i32, name: String) -> Result<Board, MyError> {
+    let board = Board::new(name);
+    match update::<Board>(client, &board, Filter::new().eq("id", &board_id)).await? {
+        0 => Err(MyError::NotFound),
+        _ => get_by_id(client, board_id).await,
+    }
 }
 
-pub async fn create_board(client: &Client, name: String) -> Result<Board, MyError> {
-    let b = Board::new(name);
-    let board = insert(client, &b).await?;
-    Ok(board)
+pub async fn set_board_archived(
+    client: &Client,
+    board_id: i32,
+    archived: bool,
+) -> Result<Board, MyError> {
+    let archived_at = match archived {
+        true => Some(Utc::now().naive_utc()),
+        false => None,
+    };
+    let stmt = client
+        .prepare("UPDATE boards SET archived_at=$1 WHERE id=$2;")
+        .await?;
+    match client.execute(&stmt, &[&archived_at, &board_id]).await? {
+        0 => Err(MyError::NotFound),
+        _ => get_by_id(client, board_id).await,
+    }
+}
+
+pub async fn delete_board(client: &Client, board_id: i32) -> Result<(), MyError> {
+    match delete::<Board>(client, Filter::new().eq("id", &board_id)).await? {
+        0 => Err(MyError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+pub async fn get_board_stats(client: &Client, board_id: i32) -> Result<BoardStats, MyError> {
+    let stmt = client
+        .prepare(
+            "SELECT \
+            (SELECT COUNT(*) FROM shapes WHERE board_id=$1), \
+            (SELECT COUNT(*) FROM events WHERE board_id=$1), \
+            (SELECT MAX(created_at) FROM events WHERE board_id=$1);",
+        )
+        .await?;
+    let row = client.query_one(&stmt, &[&board_id]).await?;
+    Ok(BoardStats {
+        shape_count: row.get(0),
+        event_count: row.get(1),
+        last_event_at: row.get(2),
+    })
 }
 
 pub async fn get_shapes(client: &Client, board_id: i32) -> Result<Vec<Shape>, MyError> {
-    let shapes = list::<Shape>(client, Some(format!("board_id={}", board_id))).await?;
+    // Creation order, which is also drawing order
+    let filter = Filter::new().eq("board_id", &board_id).order_by("id");
+    let shapes = list::<Shape>(client, filter).await?;
     Ok(shapes)
 }
 
-pub async fn create_shape(
+// Applies a shape operation and records it as the next event of the board, in a single
+// transaction. Locking the board row first makes concurrent operations on the board wait for each
+// other, so they are applied in the order of their sequence numbers
+pub async fn apply_and_record(
+    client: &mut Client,
+    board_id: i32,
+    op_id: Option<data::OperationId>,
+    message: &data::SocketMessage,
+) -> Result<Option<Event>, MyError> {
+    let text = serde_json::to_string(message).map_err(|e| MyError::InternalError(e.to_string()))?;
+    let tx = client.transaction().await?;
+    let board = tx
+        .query_opt(
+            "SELECT last_seq FROM boards WHERE id = $1 FOR UPDATE;",
+            &[&board_id],
+        )
+        .await?;
+    let last_seq: i64 = match board {
+        Some(row) => row.get(0),
+        None => return Err(MyError::NotFound),
+    };
+    if let Some(op_id) = op_id {
+        let recorded = tx
+            .query_opt(
+                "SELECT id FROM events WHERE board_id = $1 AND op_id = $2;",
+                &[&board_id, &op_id],
+            )
+            .await?;
+        if recorded.is_some() {
+            log::debug!("operation {} was already applied", op_id);
+            return Ok(None);
+        }
+    }
+    match message {
+        data::SocketMessage::ShapeCreated(entry) => {
+            let shape: Shape = entry.clone().into();
+            // Clients replay operations after reconnecting, so the same shape may be created twice
+            let inserted = tx
+                .execute(
+                    "INSERT INTO shapes (uuid, board_id, shape) VALUES ($1, $2, $3) \
+                    ON CONFLICT (uuid) DO NOTHING;",
+                    &[&shape.uuid, &board_id, &shape.shape],
+                )
+                .await?;
+            if inserted == 0 {
+                let existing = tx
+                    .query_opt(
+                        "SELECT id FROM shapes WHERE uuid = $1 AND board_id = $2;",
+                        &[&shape.uuid, &board_id],
+                    )
+                    .await?;
+                return match existing {
+                    Some(_) => Ok(None),
+                    // Taken by a shape of another board
+                    None => Err(MyError::NotFound),
+                };
+            }
+        }
+        data::SocketMessage::ShapeUpdated(entry) => {
+            let shape: Shape = entry.clone().into();
+            let updated = tx
+                .execute(
+                    "UPDATE shapes SET shape = $1 WHERE uuid = $2 AND board_id = $3;",
+                    &[&shape.shape, &shape.uuid, &board_id],
+                )
+                .await?;
+            if updated == 0 {
+                return Err(MyError::NotFound);
+            }
+        }
+        data::SocketMessage::ShapeDeleted(shape_id) => {
+            let deleted = tx
+                .execute(
+                    "DELETE FROM shapes WHERE uuid = $1 AND board_id = $2;",
+                    &[shape_id, &board_id],
+                )
+                .await?;
+            if deleted == 0 {
+                return Err(MyError::NotFound);
+            }
+        }
+        // Not persisted
+        data::SocketMessage::Pointer(_) | data::SocketMessage::Hello(_) => return Ok(None),
+        data::SocketMessage::Operation(_) => {
+            return Err(MyError::BadRequest(
+                "Operations can't be nested".to_string(),
+            ))
+        }
+    }
+    let seq = last_seq + 1;
+    tx.execute(
+        "UPDATE boards SET last_seq = $2 WHERE id = $1;",
+        &[&board_id, &seq],
+    )
+    .await?;
+    let row = tx
+        .query_one(
+            &format!(
+                "INSERT INTO events (board_id, seq, message, op_id) VALUES ($1, $2, $3, $4) \
+                RETURNING {};",
+                Event::sql_table_fields()
+            ),
+            &[&board_id, &seq, &text, &op_id],
+        )
+        .await?;
+    let event = Event::from_row_ref(&row)?;
+    tx.commit().await?;
+    Ok(Some(event))
+}
+
+// Events of the board with a sequence number greater than `since`, oldest first
+pub async fn get_events(client: &Client, board_id: i32, since: i64) -> Result<Vec<Event>, MyError> {
+    let filter = Filter::new()
+        .eq("board_id", &board_id)
+        .gt("seq", &since)
+        .order_by("seq");
+    list::<Event>(client, filter).await
+}
+
+pub async fn create_user(
+    client: &Client,
+    username: String,
+    password_hash: String,
+) -> Result<User, MyError> {
+    let user = User::new(username, password_hash);
+    match insert_if_absent(client, &user, "username").await? {
+        Some(user) => Ok(user),
+        None => Err(MyError::BadRequest("This username is taken".to_string())),
+    }
+}
+
+pub async fn get_user_by_name(client: &Client, username: &str) -> Result<User, MyError> {
+    let users = list::<User>(client, Filter::new().eq("username", &username)).await?;
+    users.into_iter().next().ok_or(MyError::NotFound)
+}
+
+pub async fn adopt_ownerless_boards(client: &Client, user_id: i32) -> Result<(), MyError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO board_members (board_id, user_id, role) \
+            SELECT id, $1, 'owner' FROM boards \
+            WHERE id NOT IN (SELECT board_id FROM board_members);",
+        )
+        .await?;
+    let adopted = client.execute(&stmt, &[&user_id]).await?;
+    if adopted > 0 {
+        log::info!("User {} now owns {} existing boards", user_id, adopted);
+    }
+    Ok(())
+}
+
+pub async fn create_session(
+    client: &Client,
+    token: String,
+    user_id: i32,
+    expires_at: NaiveDateTime,
+) -> Result<(), MyError> {
+    let session = Session {
+        id: 0,
+        token,
+        user_id,
+        created_at: Utc::now().naive_utc(),
+        expires_at,
+    };
+    insert(client, &session).await?;
+    Ok(())
+}
+
+pub async fn get_session_user(client: &Client, token: &str) -> Result<User, MyError> {
+    let stmt = client
+        .prepare(&format!(
+            "SELECT {} FROM users JOIN sessions ON sessions.user_id = users.id \
+            WHERE sessions.token = $1 AND sessions.expires_at > $2;",
+            User::sql_table_fields()
+        ))
+        .await?;
+    let now = Utc::now().naive_utc();
+    match client.query_opt(&stmt, &[&token, &now]).await? {
+        Some(row) => Ok(User::from_row_ref(&row)?),
+        None => Err(MyError::NotFound),
+    }
+}
+
+pub async fn delete_session(client: &Client, token: &str) -> Result<(), MyError> {
+    delete::<Session>(client, Filter::new().eq("token", &token)).await?;
+    Ok(())
+}
+
+pub async fn get_members(client: &Client, board_id: i32) -> Result<Vec<Member>, MyError> {
+    let stmt = client
+        .prepare(
+            "SELECT board_members.user_id, users.username, board_members.role \
+            FROM board_members JOIN users ON users.id = board_members.user_id \
+            WHERE board_members.board_id = $1 ORDER BY users.username;",
+        )
+        .await?;
+    let mut members = client
+        .query(&stmt, &[&board_id])
+        .await?
+        .iter()
+        .map(|row| {
+            let role: String = row.get(2);
+            Ok(Member {
+                user_id: row.get(0),
+                username: row.get(1),
+                role: data::Role::parse(&role)
+                    .ok_or_else(|| MyError::InternalError(format!("Unknown role {}", role)))?,
+            })
+        })
+        .collect::<Result<Vec<Member>, MyError>>()?;
+    members.sort_by_key(|m| std::cmp::Reverse(m.role));
+    Ok(members)
+}
+
+pub async fn set_member(
     client: &Client,
-    shape: data::Shape,
     board_id: i32,
-) -> Result<Shape, MyError> {
-    let mut db_shape: Shape = shape.into();
-    db_shape.board_id = board_id;
-    let s: Shape = insert::<Shape>(client, &db_shape).await?;
-    Ok(s)
+    user_id: i32,
+    role: data::Role,
+) -> Result<(), MyError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO board_members (board_id, user_id, role) VALUES ($1, $2, $3) \
+            ON CONFLICT (board_id, user_id) DO UPDATE SET role = EXCLUDED.role;",
+        )
+        .await?;
+    client
+        .execute(&stmt, &[&board_id, &user_id, &role.as_str()])
+        .await?;
+    Ok(())
+}
+
+pub async fn remove_member(client: &Client, board_id: i32, user_id: i32) -> Result<(), MyError> {
+    let stmt = client
+        .prepare("DELETE FROM board_members WHERE board_id = $1 AND user_id = $2;")
+        .await?;
+    match client.execute(&stmt, &[&board_id, &user_id]).await? {
+        0 => Err(MyError::NotFound),
+        _ => Ok(()),
+    }
 }
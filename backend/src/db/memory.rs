@@ -0,0 +1,448 @@
+use super::models::{Board, BoardStats, Event, Invite, Member, Session, Shape, User};
+use super::{imported_shape_id, BoardQuery, BoardStore, MyError};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use shared::datatypes as data;
+use std::cmp::Reverse;
+use std::sync::Mutex;
+
+// Keeps everything in memory, for tests and for trying the app out without a database
+#[derive(Default)]
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    boards: Vec<Board>,
+    shapes: Vec<Shape>,
+    events: Vec<Event>,
+    users: Vec<User>,
+    sessions: Vec<Session>,
+    members: Vec<Membership>,
+    invites: Vec<Invite>,
+    // Last id given to a row, shared by all tables
+    last_id: i32,
+}
+
+// A row of board_members
+struct Membership {
+    board_id: i32,
+    user_id: i32,
+    role: data::Role,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn board_mut(&mut self, board_id: i32) -> Result<&mut Board, MyError> {
+        match self.boards.iter_mut().find(|b| b.id == board_id) {
+            Some(board) => Ok(board),
+            None => Err(MyError::NotFound),
+        }
+    }
+
+    fn board_exists(&self, board_id: i32) -> Result<(), MyError> {
+        match self.boards.iter().any(|b| b.id == board_id) {
+            true => Ok(()),
+            false => Err(MyError::NotFound),
+        }
+    }
+
+    fn create_board(&mut self, name: String, owner_id: i32) -> Board {
+        let mut board = Board::new(name);
+        board.id = self.next_id();
+        self.boards.push(board.clone());
+        self.members.push(Membership {
+            board_id: board.id,
+            user_id: owner_id,
+            role: data::Role::Owner,
+        });
+        board
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+#[async_trait]
+impl BoardStore for MemoryStore {
+    async fn migrate(&self) -> Result<(), MyError> {
+        Ok(())
+    }
+
+    async fn get_boards(&self, query: &BoardQuery) -> Result<Vec<Board>, MyError> {
+        let tables = self.tables.lock().unwrap();
+        let search = query.search.as_ref().map(|s| s.to_lowercase());
+        let mut boards: Vec<Board> = tables
+            .boards
+            .iter()
+            .filter(|b| {
+                tables
+                    .members
+                    .iter()
+                    .any(|m| m.board_id == b.id && m.user_id == query.user_id)
+            })
+            .filter(|b| b.archived_at.is_some() == query.archived)
+            .filter(|b| {
+                search
+                    .as_ref()
+                    .is_none_or(|s| b.name.to_lowercase().contains(s))
+            })
+            .cloned()
+            .collect();
+        match query.sort {
+            data::BoardSort::Oldest => boards.sort_by_key(|b| b.id),
+            data::BoardSort::Newest => boards.sort_by_key(|b| Reverse(b.id)),
+            data::BoardSort::Name => boards.sort_by_key(|b| (b.name.to_lowercase(), b.id)),
+        }
+        if let Some(after) = &query.after {
+            boards.retain(|b| match query.sort {
+                data::BoardSort::Oldest => b.id > after.id,
+                data::BoardSort::Newest => b.id < after.id,
+                data::BoardSort::Name => {
+                    (b.name.to_lowercase(), b.id) > (after.name.to_lowercase(), after.id)
+                }
+            });
+        }
+        boards.truncate(query.limit as usize);
+        Ok(boards)
+    }
+
+    async fn get_board(&self, board_id: i32) -> Result<Board, MyError> {
+        let tables = self.tables.lock().unwrap();
+        match tables.boards.iter().find(|b| b.id == board_id) {
+            Some(board) => Ok(board.clone()),
+            None => Err(MyError::NotFound),
+        }
+    }
+
+    async fn create_board(&self, name: String, owner_id: i32) -> Result<Board, MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        Ok(tables.create_board(name, owner_id))
+    }
+
+    async fn import_board(
+        &self,
+        name: String,
+        owner_id: i32,
+        shapes: Vec<data::Shape>,
+    ) -> Result<Board, MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let board = tables.create_board(name, owner_id);
+        for (i, shape) in shapes.into_iter().enumerate() {
+            let mut db_shape: Shape = data::ShapeEntry {
+                id: imported_shape_id(board.id, i),
+                shape,
+            }
+            .into();
+            db_shape.id = tables.next_id();
+            db_shape.board_id = board.id;
+            tables.shapes.push(db_shape);
+        }
+        Ok(board)
+    }
+
+    async fn rename_board(&self, board_id: i32, name: String) -> Result<Board, MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let board = tables.board_mut(board_id)?;
+        board.name = name;
+        Ok(board.clone())
+    }
+
+    async fn set_board_archived(&self, board_id: i32, archived: bool) -> Result<Board, MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let board = tables.board_mut(board_id)?;
+        board.archived_at = match archived {
+            true => Some(Utc::now().naive_utc()),
+            false => None,
+        };
+        Ok(board.clone())
+    }
+
+    async fn delete_board(&self, board_id: i32) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.board_exists(board_id)?;
+        tables.boards.retain(|b| b.id != board_id);
+        tables.shapes.retain(|s| s.board_id != board_id);
+        tables.events.retain(|e| e.board_id != board_id);
+        tables.members.retain(|m| m.board_id != board_id);
+        tables.invites.retain(|i| i.board_id != board_id);
+        Ok(())
+    }
+
+    async fn get_board_stats(&self, board_id: i32) -> Result<BoardStats, MyError> {
+        let tables = self.tables.lock().unwrap();
+        let events = tables.events.iter().filter(|e| e.board_id == board_id);
+        Ok(BoardStats {
+            shape_count: tables
+                .shapes
+                .iter()
+                .filter(|s| s.board_id == board_id)
+                .count() as i64,
+            event_count: events.clone().count() as i64,
+            last_event_at: events.map(|e| e.created_at).max(),
+        })
+    }
+
+    async fn get_shapes(&self, board_id: i32) -> Result<Vec<Shape>, MyError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .shapes
+            .iter()
+            .filter(|s| s.board_id == board_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn apply_and_record(
+        &self,
+        board_id: i32,
+        op_id: Option<data::OperationId>,
+        message: &data::SocketMessage,
+    ) -> Result<Option<Event>, MyError> {
+        let text =
+            serde_json::to_string(message).map_err(|e| MyError::InternalError(e.to_string()))?;
+        // Held until the event is recorded, like the board row lock of the database
+        let mut tables = self.tables.lock().unwrap();
+        tables.board_exists(board_id)?;
+        let recorded = |e: &Event| e.board_id == board_id && e.op_id == op_id;
+        if op_id.is_some() && tables.events.iter().any(recorded) {
+            return Ok(None);
+        }
+        match message {
+            data::SocketMessage::ShapeCreated(entry) => {
+                if let Some(existing) = tables.shapes.iter().find(|s| s.uuid == entry.id) {
+                    return match existing.board_id == board_id {
+                        true => Ok(None),
+                        false => Err(MyError::NotFound),
+                    };
+                }
+                let mut shape: Shape = entry.clone().into();
+                shape.id = tables.next_id();
+                shape.board_id = board_id;
+                tables.shapes.push(shape);
+            }
+            data::SocketMessage::ShapeUpdated(entry) => {
+                let updated: Shape = entry.clone().into();
+                let existing = tables
+                    .shapes
+                    .iter_mut()
+                    .find(|s| s.uuid == updated.uuid && s.board_id == board_id)
+                    .ok_or(MyError::NotFound)?;
+                existing.shape = updated.shape;
+            }
+            data::SocketMessage::ShapeDeleted(shape_id) => {
+                let count = tables.shapes.len();
+                tables
+                    .shapes
+                    .retain(|s| !(s.uuid == *shape_id && s.board_id == board_id));
+                if tables.shapes.len() == count {
+                    return Err(MyError::NotFound);
+                }
+            }
+            data::SocketMessage::Pointer(_) | data::SocketMessage::Hello(_) => return Ok(None),
+            data::SocketMessage::Operation(_) => {
+                return Err(MyError::BadRequest(
+                    "Operations can't be nested".to_string(),
+                ))
+            }
+        }
+        let id = tables.next_id();
+        let board = tables.board_mut(board_id)?;
+        board.last_seq += 1;
+        let event = Event {
+            id,
+            board_id,
+            seq: board.last_seq,
+            created_at: Utc::now().naive_utc(),
+            message: text,
+            op_id,
+        };
+        tables.events.push(event.clone());
+        Ok(Some(event))
+    }
+
+    async fn get_events(&self, board_id: i32, since: i64) -> Result<Vec<Event>, MyError> {
+        let tables = self.tables.lock().unwrap();
+        // Events are appended in sequence order
+        Ok(tables
+            .events
+            .iter()
+            .filter(|e| e.board_id == board_id && e.seq > since)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_user(&self, username: String, password_hash: String) -> Result<User, MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.users.iter().any(|u| u.username == username) {
+            return Err(MyError::BadRequest("This username is taken".to_string()));
+        }
+        let mut user = User::new(username, password_hash);
+        user.id = tables.next_id();
+        tables.users.push(user.clone());
+        Ok(user)
+    }
+
+    async fn get_user_by_name(&self, username: &str) -> Result<User, MyError> {
+        let tables = self.tables.lock().unwrap();
+        match tables.users.iter().find(|u| u.username == username) {
+            Some(user) => Ok(user.clone()),
+            None => Err(MyError::NotFound),
+        }
+    }
+
+    async fn adopt_ownerless_boards(&self, user_id: i32) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let ownerless: Vec<i32> = tables
+            .boards
+            .iter()
+            .map(|b| b.id)
+            .filter(|id| !tables.members.iter().any(|m| m.board_id == *id))
+            .collect();
+        for board_id in ownerless {
+            tables.members.push(Membership {
+                board_id,
+                user_id,
+                role: data::Role::Owner,
+            });
+        }
+        Ok(())
+    }
+
+    async fn create_session(
+        &self,
+        token: String,
+        user_id: i32,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let session = Session {
+            id: tables.next_id(),
+            token,
+            user_id,
+            created_at: Utc::now().naive_utc(),
+            expires_at,
+        };
+        tables.sessions.push(session);
+        Ok(())
+    }
+
+    async fn get_session_user(&self, token: &str) -> Result<User, MyError> {
+        let tables = self.tables.lock().unwrap();
+        let now = Utc::now().naive_utc();
+        tables
+            .sessions
+            .iter()
+            .find(|s| s.token == token && s.expires_at > now)
+            .and_then(|s| tables.users.iter().find(|u| u.id == s.user_id))
+            .cloned()
+            .ok_or(MyError::NotFound)
+    }
+
+    async fn delete_session(&self, token: &str) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.sessions.retain(|s| s.token != token);
+        Ok(())
+    }
+
+    async fn get_members(&self, board_id: i32) -> Result<Vec<Member>, MyError> {
+        let tables = self.tables.lock().unwrap();
+        let mut members: Vec<Member> = tables
+            .members
+            .iter()
+            .filter(|m| m.board_id == board_id)
+            .filter_map(|m| {
+                let user = tables.users.iter().find(|u| u.id == m.user_id)?;
+                Some(Member {
+                    user_id: m.user_id,
+                    username: user.username.clone(),
+                    role: m.role,
+                })
+            })
+            .collect();
+        members.sort_by(|a, b| (Reverse(a.role), &a.username).cmp(&(Reverse(b.role), &b.username)));
+        Ok(members)
+    }
+
+    async fn set_member(
+        &self,
+        board_id: i32,
+        user_id: i32,
+        role: data::Role,
+    ) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.board_exists(board_id)?;
+        match tables
+            .members
+            .iter_mut()
+            .find(|m| m.board_id == board_id && m.user_id == user_id)
+        {
+            Some(member) => member.role = role,
+            None => tables.members.push(Membership {
+                board_id,
+                user_id,
+                role,
+            }),
+        }
+        Ok(())
+    }
+
+    async fn remove_member(&self, board_id: i32, user_id: i32) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let count = tables.members.len();
+        tables
+            .members
+            .retain(|m| !(m.board_id == board_id && m.user_id == user_id));
+        match tables.members.len() < count {
+            true => Ok(()),
+            false => Err(MyError::NotFound),
+        }
+    }
+
+    async fn create_invite(&self, invite: Invite) -> Result<Invite, MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.board_exists(invite.board_id)?;
+        let mut invite = invite;
+        invite.id = tables.next_id();
+        tables.invites.push(invite.clone());
+        Ok(invite)
+    }
+
+    async fn get_invites(&self, board_id: i32) -> Result<Vec<Invite>, MyError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .invites
+            .iter()
+            .filter(|i| i.board_id == board_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_invite(&self, token: &str) -> Result<Invite, MyError> {
+        let tables = self.tables.lock().unwrap();
+        match tables.invites.iter().find(|i| i.token == token) {
+            Some(invite) => Ok(invite.clone()),
+            None => Err(MyError::NotFound),
+        }
+    }
+
+    async fn delete_invite(&self, board_id: i32, invite_id: i32) -> Result<(), MyError> {
+        let mut tables = self.tables.lock().unwrap();
+        let count = tables.invites.len();
+        tables
+            .invites
+            .retain(|i| !(i.id == invite_id && i.board_id == board_id));
+        match tables.invites.len() < count {
+            true => Ok(()),
+            false => Err(MyError::NotFound),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::{Client, MyError};
+
+struct Migration {
+    version: i32,
+    name: &'static str,
+    sql: &'static str,
+}
+
+// Applied in order, each at most once. Never edit a migration that has been released, add a new
+// one instead
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "shape_uuids",
+        sql: include_str!("../../migrations/0002_shape_uuids.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "events",
+        sql: include_str!("../../migrations/0003_events.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "board_archive",
+        sql: include_str!("../../migrations/0004_board_archive.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "accounts",
+        sql: include_str!("../../migrations/0005_accounts.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "invites",
+        sql: include_str!("../../migrations/0006_invites.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "operation_ids",
+        sql: include_str!("../../migrations/0007_operation_ids.sql"),
+    },
+];
+
+// Applies the migrations missing from the schema_migrations table, each in its own transaction,
+// and returns their versions
+pub async fn run(client: &mut Client) -> Result<Vec<i32>, MyError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INT PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+            );",
+        )
+        .await?;
+    let mut applied = vec![];
+    for migration in MIGRATIONS {
+        let tx = client.transaction().await?;
+        // Another backend starting at the same time waits for us, then sees what we applied
+        tx.batch_execute("LOCK TABLE schema_migrations IN EXCLUSIVE MODE;")
+            .await?;
+        let done = tx
+            .query_opt(
+                "SELECT version FROM schema_migrations WHERE version=$1;",
+                &[&migration.version],
+            )
+            .await?;
+        if done.is_some() {
+            continue;
+        }
+        log::info!(
+            "Applying migration {:04}_{}",
+            migration.version,
+            migration.name
+        );
+        tx.batch_execute(migration.sql).await?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES ($1, $2);",
+            &[&migration.version, &migration.name],
+        )
+        .await?;
+        tx.commit().await?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
@@ -0,0 +1,6 @@
+pub mod auth;
+pub mod config;
+pub mod db;
+pub mod rest_handlers;
+pub mod thumbnails;
+pub mod ws_handlers;
@@ -1,43 +1,106 @@
 use actix_files as fs;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::{middleware::Logger, web, App, HttpServer};
-mod db;
-mod rest_handlers;
-mod ws_handlers;
+use backend::config::{Args, Command, Config};
+use backend::{auth, db, rest_handlers, thumbnails, ws_handlers};
+use clap::Parser;
+use std::process::exit;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    simple_logger::init_with_level(log::Level::Debug).unwrap();
+    let args = Args::parse();
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            exit(2);
+        }
+    };
+    simple_logger::init_with_level(config.log_level).unwrap();
+    if let Some(password) = &args.db_password {
+        log::warn!("DB_PASSWORD is deprecated, set the password in LIVEBOARD_DATABASE_URL instead");
+        if config.database_url.get_password() != Some(password.as_bytes()) {
+            log::warn!("Ignoring DB_PASSWORD, the database URL has a password");
+        }
+    }
 
-    let ws_state = web::Data::new(ws_handlers::make_state());
-    let db_state = web::Data::new(db::make_state());
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat {
+        interval: config.ws_ping_interval,
+        timeout: config.ws_timeout,
+    }));
+    let thumbnail_cache = web::Data::new(thumbnails::make_cache());
+    let db_state = match db::make_state(&config) {
+        Ok(db_state) => web::Data::new(db_state),
+        Err(e) => {
+            log::error!("Configuration error: {}", e);
+            exit(2);
+        }
+    };
+    if let Err(e) = db_state.store.migrate().await {
+        log::error!("Error applying migrations: {}", e);
+        exit(1);
+    }
+    if let Some(Command::Migrate) = args.command {
+        return Ok(());
+    }
+
+    if !config.static_dir.is_dir() {
+        log::warn!(
+            "Static directory {} not found, only the API will be served",
+            config.static_dir.display()
+        );
+    }
+    let static_dir = config.static_dir.clone();
 
     HttpServer::new(move || {
         App::new()
             .app_data(ws_state.clone())
+            .app_data(thumbnail_cache.clone())
             .app_data(db_state.clone())
             .service(
                 web::scope("/api")
+                    .configure(rest_handlers::extractor_errors)
+                    .service(auth::register)
+                    .service(auth::login)
+                    .service(auth::logout)
+                    .service(auth::me)
                     .service(rest_handlers::create_board)
                     .service(rest_handlers::list_boards)
                     .service(rest_handlers::get_board)
+                    .service(rest_handlers::get_board_shapes)
+                    .service(rest_handlers::export_board_svg)
+                    .service(rest_handlers::get_board_thumbnail)
+                    .service(rest_handlers::export_board)
+                    .service(rest_handlers::import_board)
+                    .service(rest_handlers::update_board)
+                    .service(rest_handlers::delete_board)
+                    .service(rest_handlers::restore_board)
+                    .service(rest_handlers::get_board_events)
+                    .service(rest_handlers::list_members)
+                    .service(rest_handlers::set_member)
+                    .service(rest_handlers::remove_member)
+                    .service(rest_handlers::create_invite)
+                    .service(rest_handlers::list_invites)
+                    .service(rest_handlers::delete_invite)
+                    .service(rest_handlers::get_invite)
                     .service(ws_handlers::ws_for_board),
             )
-            .service(
-                fs::Files::new("/", "../frontend/dist")
+            .service({
+                let index = static_dir.join("index.html");
+                fs::Files::new("/", &static_dir)
                     .index_file("index.html")
-                    .default_handler(|req: ServiceRequest| {
+                    .default_handler(move |req: ServiceRequest| {
                         let (http_req, _payload) = req.into_parts();
-                        async {
-                            let response = fs::NamedFile::open("../frontend/dist/index.html")?
-                                .into_response(&http_req);
+                        let index = index.clone();
+                        async move {
+                            let response = fs::NamedFile::open(index)?.into_response(&http_req);
                             Ok(ServiceResponse::new(http_req, response))
                         }
-                    }),
-            )
+                    })
+            })
             .wrap(Logger::default())
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind(&config.bind)?
     .run()
     .await
 }
@@ -1,43 +1,548 @@
-use super::db;
-use super::db::{get_boards, get_shapes, State};
-use actix_web::{get, post, web, Responder, Result};
+use super::auth::{self, CurrentUser};
+use super::db::models::Invite;
+use super::db::{BoardCursor, BoardQuery, MyError, State};
+use super::{thumbnails, ws_handlers};
+use actix_web::http::header::{
+    self, CacheControl, CacheDirective, ContentDisposition, DispositionParam, DispositionType,
+};
+use actix_web::{delete, error, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
 use shared::datatypes as data;
-use shared::datatypes::CreateBoardRequest;
+use shared::datatypes::{
+    BoardSort, CreateBoardRequest, Role, UpdateBoardRequest, DEFAULT_BOARD_PAGE_SIZE,
+    MAX_BOARD_PAGE_SIZE,
+};
+use shared::render;
+
+// Board archives can be large
+const MAX_JSON_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+// Report malformed bodies, paths and query strings like any other error
+pub fn extractor_errors(cfg: &mut web::ServiceConfig) {
+    fn bad_request(err: impl std::fmt::Display, _req: &HttpRequest) -> error::Error {
+        MyError::BadRequest(err.to_string()).into()
+    }
+    let json_config = web::JsonConfig::default()
+        .limit(MAX_JSON_BODY_SIZE)
+        .error_handler(bad_request);
+    cfg.app_data(json_config)
+        .app_data(web::PathConfig::default().error_handler(bad_request))
+        .app_data(web::QueryConfig::default().error_handler(bad_request));
+}
 
 #[post("/boards")]
-async fn create_board(
+pub async fn create_board(
     db_state: web::Data<State>,
+    user: CurrentUser,
     data: web::Json<CreateBoardRequest>,
-) -> Result<impl Responder> {
-    let client = db_state.as_ref().pool.clone().get().await.unwrap();
-    let board: data::Board = db::create_board(&client, data.name.clone())
-        .await
-        .unwrap()
+) -> Result<impl Responder, MyError> {
+    data.validate().map_err(MyError::BadRequest)?;
+    let board: data::Board = db_state
+        .store
+        .create_board(data.name.trim().to_string(), user.0.id)
+        .await?
         .into();
-    println!("New board {:?}", board);
+    log::info!("New board {:?}", board);
     Ok(web::Json(board))
 }
 
+#[derive(Deserialize)]
+pub struct ListBoardsQuery {
+    // List the archived boards instead of the active ones
+    #[serde(default)]
+    archived: bool,
+    // Only boards whose name contains it, ignoring case
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    sort: BoardSort,
+    // `next_cursor` of the previous page
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
 #[get("/boards")]
-async fn list_boards(db_state: web::Data<State>) -> Result<impl Responder> {
-    let client = db_state.as_ref().pool.clone().get().await.unwrap();
-    let boards: Vec<data::Board> = get_boards(&client)
-        .await
-        .unwrap()
+pub async fn list_boards(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    query: web::Query<ListBoardsQuery>,
+) -> Result<impl Responder, MyError> {
+    let limit = query.limit.unwrap_or(DEFAULT_BOARD_PAGE_SIZE);
+    if !(1..=MAX_BOARD_PAGE_SIZE).contains(&limit) {
+        return Err(MyError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_BOARD_PAGE_SIZE
+        )));
+    }
+    let search = query.query.trim();
+    let board_query = BoardQuery {
+        user_id: user.0.id,
+        archived: query.archived,
+        search: Some(search.to_string()).filter(|s| !s.is_empty()),
+        sort: query.sort,
+        after: query
+            .cursor
+            .as_deref()
+            .filter(|c| !c.is_empty())
+            .map(BoardCursor::decode)
+            .transpose()?,
+        // One more than asked for, to know whether there is a next page
+        limit: limit + 1,
+    };
+    let mut boards = db_state.store.get_boards(&board_query).await?;
+    let mut next_cursor = None;
+    if boards.len() as i64 > limit {
+        boards.truncate(limit as usize);
+        next_cursor = boards.last().map(|b| BoardCursor::from(b).encode());
+    }
+    Ok(web::Json(data::BoardPage {
+        boards: boards.into_iter().map(|b| b.into()).collect(),
+        next_cursor,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct InviteQuery {
+    // Token of an invite to the board, for those who aren't members
+    invite: Option<String>,
+}
+
+impl InviteQuery {
+    pub fn token(&self) -> Option<&str> {
+        self.invite.as_deref()
+    }
+}
+
+#[get("/boards/{id}")]
+pub async fn get_board(
+    db_state: web::Data<State>,
+    user: Option<CurrentUser>,
+    invite: web::Query<InviteQuery>,
+    path: web::Path<(i32,)>,
+) -> Result<impl Responder, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    let role = db_state
+        .check_access(
+            &board,
+            user.as_ref().map(|u| &u.0),
+            invite.token(),
+            Role::Viewer,
+        )
+        .await?;
+    let stats = db_state.store.get_board_stats(board.id).await?;
+    let created_at = board.created_at;
+    Ok(web::Json(data::BoardDetails {
+        board: board.into(),
+        shape_count: stats.shape_count,
+        event_count: stats.event_count,
+        last_modified: stats.last_event_at.unwrap_or(created_at),
+        role,
+    }))
+}
+
+#[get("/boards/{id}/shapes")]
+pub async fn get_board_shapes(
+    db_state: web::Data<State>,
+    user: Option<CurrentUser>,
+    invite: web::Query<InviteQuery>,
+    path: web::Path<(i32,)>,
+) -> Result<impl Responder, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state
+        .check_access(
+            &board,
+            user.as_ref().map(|u| &u.0),
+            invite.token(),
+            Role::Viewer,
+        )
+        .await?;
+    // Read after the board, so they include at least the events up to its last_seq
+    let shapes: Vec<data::ShapeEntry> = db_state
+        .store
+        .get_shapes(board.id)
+        .await?
+        .into_iter()
+        .map(data::ShapeEntry::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok(web::Json(data::BoardShapes {
+        shapes,
+        last_seq: board.last_seq,
+    }))
+}
+
+// World units left around the shapes of exported boards
+const EXPORT_MARGIN: f64 = 10.0;
+
+#[get("/boards/{id}/export.svg")]
+pub async fn export_board_svg(
+    db_state: web::Data<State>,
+    user: Option<CurrentUser>,
+    invite: web::Query<InviteQuery>,
+    path: web::Path<(i32,)>,
+) -> Result<HttpResponse, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state
+        .check_access(
+            &board,
+            user.as_ref().map(|u| &u.0),
+            invite.token(),
+            Role::Viewer,
+        )
+        .await?;
+    let shapes: Vec<data::Shape> = db_state
+        .store
+        .get_shapes(board.id)
+        .await?
+        .into_iter()
+        .map(|s| data::ShapeEntry::try_from(s).map(|e| e.shape))
+        .collect::<Result<_, _>>()?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header(attachment(format!("board-{}.svg", board.id)))
+        .body(render::to_svg(&shapes, EXPORT_MARGIN)))
+}
+
+#[get("/boards/{id}/thumbnail.png")]
+pub async fn get_board_thumbnail(
+    db_state: web::Data<State>,
+    cache: web::Data<thumbnails::Cache>,
+    user: CurrentUser,
+    req: HttpRequest,
+    path: web::Path<(i32,)>,
+) -> Result<HttpResponse, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Viewer).await?;
+    // Every change to a board is recorded as an event, so their number identifies its version
+    let version = db_state.store.get_board_stats(board.id).await?.event_count;
+    let etag = format!("\"{}-{}\"", board.id, version);
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH);
+    if if_none_match.is_some_and(|value| value == etag.as_str()) {
+        return Ok(HttpResponse::NotModified().finish());
+    }
+    let png = match cache.get(board.id, version) {
+        Some(png) => png,
+        None => {
+            let shapes: Vec<data::Shape> = db_state
+                .store
+                .get_shapes(board.id)
+                .await?
+                .into_iter()
+                .map(|s| data::ShapeEntry::try_from(s).map(|e| e.shape))
+                .collect::<Result<_, _>>()?;
+            let png = web::block(move || thumbnails::render(&shapes))
+                .await
+                .map_err(|e| MyError::InternalError(e.to_string()))?
+                .map_err(MyError::InternalError)?;
+            cache.insert(board.id, version, png.clone());
+            png
+        }
+    };
+    // Browsers check with the ETag whether the board changed before using their copy
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header((header::ETAG, etag))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(png))
+}
+
+#[get("/boards/{id}/export")]
+pub async fn export_board(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+) -> Result<HttpResponse, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Viewer).await?;
+    let shapes: Vec<data::ShapeEntry> = db_state
+        .store
+        .get_shapes(board.id)
+        .await?
+        .into_iter()
+        .map(data::ShapeEntry::try_from)
+        .collect::<Result<_, _>>()?;
+    let archive = data::BoardArchive {
+        version: data::BOARD_ARCHIVE_VERSION,
+        name: board.name,
+        created_at: board.created_at,
+        shapes,
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(attachment(format!("board-{}.json", board.id)))
+        .json(archive))
+}
+
+#[post("/boards/import")]
+pub async fn import_board(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    archive: web::Json<data::BoardArchive>,
+) -> Result<impl Responder, MyError> {
+    archive.validate().map_err(MyError::BadRequest)?;
+    let archive = archive.into_inner();
+    let shapes = archive.shapes.into_iter().map(|e| e.shape).collect();
+    let board: data::Board = db_state
+        .store
+        .import_board(archive.name.trim().to_string(), user.0.id, shapes)
+        .await?
+        .into();
+    log::info!("Imported board {:?}", board);
+    Ok(web::Json(board))
+}
+
+// Makes browsers download the response as `filename`
+fn attachment(filename: String) -> ContentDisposition {
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(filename)],
+    }
+}
+
+#[patch("/boards/{id}")]
+pub async fn update_board(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+    data: web::Json<UpdateBoardRequest>,
+) -> Result<impl Responder, MyError> {
+    data.validate().map_err(MyError::BadRequest)?;
+    let mut board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    if let Some(name) = &data.name {
+        board = db_state
+            .store
+            .rename_board(board.id, name.trim().to_string())
+            .await?;
+    }
+    Ok(web::Json(data::Board::from(board)))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteBoardQuery {
+    // Delete an archived board for good, instead of archiving an active one
+    #[serde(default)]
+    permanent: bool,
+}
+
+#[delete("/boards/{id}")]
+pub async fn delete_board(
+    db_state: web::Data<State>,
+    ws_state: web::Data<ws_handlers::State>,
+    thumbnails: web::Data<thumbnails::Cache>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+    query: web::Query<DeleteBoardQuery>,
+) -> Result<HttpResponse, MyError> {
+    if query.permanent {
+        let board = db_state.store.get_board(path.0).await?;
+        db_state.check_role(&board, &user.0, Role::Owner).await?;
+        if board.archived_at.is_none() {
+            return Err(MyError::BadRequest(
+                "Only archived boards can be deleted permanently".to_string(),
+            ));
+        }
+        db_state.store.delete_board(board.id).await?;
+        thumbnails.remove(board.id);
+        return Ok(HttpResponse::NoContent().finish());
+    }
+    let board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    let board = db_state.store.set_board_archived(board.id, true).await?;
+    ws_state.close_room(board.id);
+    Ok(HttpResponse::Ok().json(data::Board::from(board)))
+}
+
+#[post("/boards/{id}/restore")]
+pub async fn restore_board(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+) -> Result<impl Responder, MyError> {
+    let board = db_state.store.get_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    let board = db_state.store.set_board_archived(board.id, false).await?;
+    Ok(web::Json(data::Board::from(board)))
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    // Only return events after this sequence number. All of them by default
+    #[serde(default)]
+    since: i64,
+}
+
+#[get("/boards/{id}/events")]
+pub async fn get_board_events(
+    db_state: web::Data<State>,
+    user: Option<CurrentUser>,
+    invite: web::Query<InviteQuery>,
+    path: web::Path<(i32,)>,
+    query: web::Query<EventsQuery>,
+) -> Result<impl Responder, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state
+        .check_access(
+            &board,
+            user.as_ref().map(|u| &u.0),
+            invite.token(),
+            Role::Viewer,
+        )
+        .await?;
+    let events: Vec<data::BoardEvent> = db_state
+        .store
+        .get_events(board.id, query.since)
+        .await?
         .into_iter()
-        .map(|b| b.into())
+        .map(data::BoardEvent::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok(web::Json(events))
+}
+
+#[get("/boards/{id}/members")]
+pub async fn list_members(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+) -> Result<impl Responder, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Viewer).await?;
+    let members: Vec<data::Member> = db_state
+        .store
+        .get_members(board.id)
+        .await?
+        .into_iter()
+        .map(|m| m.into())
         .collect();
-    Ok(web::Json(boards))
+    Ok(web::Json(members))
 }
 
-#[get("/boards/{id}")]
-async fn get_board(db_state: web::Data<State>, path: web::Path<(i32,)>) -> Result<impl Responder> {
-    let client = db_state.as_ref().pool.clone().get().await.unwrap();
-    let shapes: Vec<data::Shape> = get_shapes(&client, path.0)
-        .await
-        .unwrap()
+// Shares the board with a user, or changes their role. Their open sockets reconnect with it
+#[put("/boards/{id}/members/{username}")]
+pub async fn set_member(
+    db_state: web::Data<State>,
+    ws_state: web::Data<ws_handlers::State>,
+    user: CurrentUser,
+    path: web::Path<(i32, String)>,
+    data: web::Json<data::SetMemberRequest>,
+) -> Result<HttpResponse, MyError> {
+    let (board_id, username) = path.into_inner();
+    let board = db_state.get_active_board(board_id).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    if data.role == Role::Owner {
+        return Err(MyError::BadRequest(
+            "A board has a single owner".to_string(),
+        ));
+    }
+    let member = match db_state.store.get_user_by_name(&username).await {
+        Ok(member) => member,
+        Err(MyError::NotFound) => {
+            return Err(MyError::BadRequest(format!("Unknown user {}", username)))
+        }
+        Err(e) => return Err(e),
+    };
+    if member.id == user.0.id {
+        return Err(MyError::BadRequest(
+            "The owner's role can't be changed".to_string(),
+        ));
+    }
+    db_state
+        .store
+        .set_member(board.id, member.id, data.role)
+        .await?;
+    ws_state.change_member(board.id, member.id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Owners remove members, and members can leave. Their open sockets are disconnected
+#[delete("/boards/{id}/members/{username}")]
+pub async fn remove_member(
+    db_state: web::Data<State>,
+    ws_state: web::Data<ws_handlers::State>,
+    user: CurrentUser,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, MyError> {
+    let (board_id, username) = path.into_inner();
+    let board = db_state.get_active_board(board_id).await?;
+    let role = db_state.check_role(&board, &user.0, Role::Viewer).await?;
+    if username != user.0.username && role != Role::Owner {
+        return Err(MyError::Forbidden(
+            "Only the owner can remove other members".to_string(),
+        ));
+    }
+    if username == user.0.username && role == Role::Owner {
+        return Err(MyError::BadRequest(
+            "The owner can't leave their board".to_string(),
+        ));
+    }
+    let member = db_state.store.get_user_by_name(&username).await?;
+    db_state.store.remove_member(board.id, member.id).await?;
+    ws_state.change_member(board.id, member.id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/boards/{id}/invites")]
+pub async fn create_invite(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+    data: web::Json<data::CreateInviteRequest>,
+) -> Result<impl Responder, MyError> {
+    data.validate().map_err(MyError::BadRequest)?;
+    let board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    let expires_at = data
+        .expires_in_hours
+        .map(|hours| Utc::now().naive_utc() + Duration::hours(hours));
+    let invite = Invite::new(board.id, auth::new_token(), data.role, expires_at);
+    let invite = db_state.store.create_invite(invite).await?;
+    Ok(web::Json(data::Invite::from(invite)))
+}
+
+#[get("/boards/{id}/invites")]
+pub async fn list_invites(
+    db_state: web::Data<State>,
+    user: CurrentUser,
+    path: web::Path<(i32,)>,
+) -> Result<impl Responder, MyError> {
+    let board = db_state.get_active_board(path.0).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    let invites: Vec<data::Invite> = db_state
+        .store
+        .get_invites(board.id)
+        .await?
         .into_iter()
-        .map(|s| s.into())
+        .map(|i| i.into())
         .collect();
-    Ok(web::Json(shapes))
+    Ok(web::Json(invites))
+}
+
+// Revokes the invite, and disconnects those who joined with it
+#[delete("/boards/{id}/invites/{invite_id}")]
+pub async fn delete_invite(
+    db_state: web::Data<State>,
+    ws_state: web::Data<ws_handlers::State>,
+    user: CurrentUser,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, MyError> {
+    let (board_id, invite_id) = path.into_inner();
+    let board = db_state.get_active_board(board_id).await?;
+    db_state.check_role(&board, &user.0, Role::Owner).await?;
+    db_state.store.delete_invite(board.id, invite_id).await?;
+    ws_state.revoke_invite(board.id, invite_id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Where an invite leads, for anyone who has it
+#[get("/invites/{token}")]
+pub async fn get_invite(
+    db_state: web::Data<State>,
+    path: web::Path<(String,)>,
+) -> Result<impl Responder, MyError> {
+    let invite = db_state.get_valid_invite(&path.0).await?;
+    let board = db_state.get_active_board(invite.board_id).await?;
+    Ok(web::Json(data::InviteTarget {
+        board_id: board.id,
+        board_name: board.name,
+        role: invite.role(),
+    }))
 }
@@ -0,0 +1,131 @@
+// Small PNG previews of boards, shown on the boards list
+use shared::datatypes::Shape;
+use shared::geometry::{Bounds, Viewport};
+use shared::render::{Primitive, Style};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tiny_skia::{
+    Color, FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform,
+};
+
+pub const WIDTH: u32 = 200;
+pub const HEIGHT: u32 = 150;
+// Screen pixels left around the shapes
+const MARGIN: f64 = 10.0;
+
+// Rendered thumbnails by board id, with the version of the board they show
+pub struct Cache {
+    entries: Mutex<HashMap<i32, (i64, Vec<u8>)>>,
+}
+
+pub fn make_cache() -> Cache {
+    Cache {
+        entries: Mutex::new(HashMap::new()),
+    }
+}
+
+impl Cache {
+    pub fn get(&self, board_id: i32, version: i64) -> Option<Vec<u8>> {
+        match self.entries.lock().unwrap().get(&board_id) {
+            Some((cached_version, png)) if *cached_version == version => Some(png.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, board_id: i32, version: i64, png: Vec<u8>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(board_id, (version, png));
+    }
+
+    pub fn remove(&self, board_id: i32) {
+        self.entries.lock().unwrap().remove(&board_id);
+    }
+}
+
+// Draws `shapes` like the canvas does, zoomed to fit, on a white background
+pub fn render(shapes: &[Shape]) -> Result<Vec<u8>, String> {
+    let mut pixmap = Pixmap::new(WIDTH, HEIGHT).ok_or("Invalid thumbnail size")?;
+    pixmap.fill(Color::WHITE);
+    if let Some(bounds) = Bounds::of_shapes(shapes) {
+        let Viewport { origin, scale } =
+            Viewport::fit(&bounds, WIDTH as f64, HEIGHT as f64, MARGIN);
+        let transform = Transform::from_row(
+            scale as f32,
+            0.0,
+            0.0,
+            scale as f32,
+            (-origin.x * scale) as f32,
+            (-origin.y * scale) as f32,
+        );
+        for shape in shapes {
+            draw_shape(&mut pixmap, shape, transform);
+        }
+    }
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+fn draw_shape(pixmap: &mut Pixmap, shape: &Shape, transform: Transform) {
+    let Style {
+        color,
+        stroke_width,
+    } = shape.style();
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.r, color.g, color.b, 255);
+    paint.anti_alias = true;
+    for primitive in shape.primitives() {
+        let mut stroke = Stroke {
+            width: stroke_width as f32,
+            ..Stroke::default()
+        };
+        // Degenerate primitives, like zero-sized rectangles, are skipped
+        let path = match primitive {
+            Primitive::Disc { center, radius } => {
+                if let Some(path) =
+                    PathBuilder::from_circle(center.x as f32, center.y as f32, radius as f32)
+                {
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+                }
+                continue;
+            }
+            Primitive::Rect {
+                corner,
+                width,
+                height,
+            } => Rect::from_xywh(
+                corner.x as f32,
+                corner.y as f32,
+                width as f32,
+                height as f32,
+            )
+            .map(PathBuilder::from_rect),
+            Primitive::Ellipse {
+                center,
+                radius_x,
+                radius_y,
+            } => Rect::from_xywh(
+                (center.x - radius_x) as f32,
+                (center.y - radius_y) as f32,
+                (radius_x * 2.0) as f32,
+                (radius_y * 2.0) as f32,
+            )
+            .and_then(PathBuilder::from_oval),
+            Primitive::Polyline(points) => {
+                stroke.line_cap = LineCap::Round;
+                stroke.line_join = LineJoin::Round;
+                let mut builder = PathBuilder::new();
+                for (i, point) in points.iter().enumerate() {
+                    match i {
+                        0 => builder.move_to(point.x as f32, point.y as f32),
+                        _ => builder.line_to(point.x as f32, point.y as f32),
+                    }
+                }
+                builder.finish()
+            }
+        };
+        if let Some(path) = path {
+            pixmap.stroke_path(&path, &paint, &stroke, transform, None);
+        }
+    }
+}
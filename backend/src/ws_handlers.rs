@@ -1,66 +1,306 @@
+use crate::auth::{self, CurrentUser};
 use crate::db;
-use actix::{Actor, Addr, AsyncContext, Handler, Message as ActixMessage, StreamHandler};
-use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use crate::rest_handlers::InviteQuery;
+use actix::{
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Handler, Message as ActixMessage,
+    StreamHandler,
+};
+use actix_web::{get, http::header, web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use shared::datatypes::{Shape, SocketMessage};
+use serde::Deserialize;
+use shared::datatypes::{
+    BoardEvent, OperationId, Participant, Role, ServerMessage, SocketMessage, MAX_USERNAME_LENGTH,
+};
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 pub struct State {
-    pub clients: Mutex<Vec<Addr<WsActor>>>,
+    // Connected clients, grouped by the board they are viewing
+    rooms: Mutex<HashMap<i32, Vec<Client>>>,
+    heartbeat: Heartbeat,
 }
 
-pub fn make_state() -> State {
+// Clients are pinged every `interval`, and disconnected when nothing came from them for `timeout`,
+// so half-open connections don't stay in their room forever
+#[derive(Clone, Copy, Debug)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+struct Client {
+    addr: Addr<WsActor>,
+    // Known once the client said Hello
+    participant: Option<Participant>,
+}
+
+pub fn make_state(heartbeat: Heartbeat) -> State {
     State {
-        clients: Mutex::new(Vec::new()),
+        rooms: Mutex::new(HashMap::new()),
+        heartbeat,
+    }
+}
+
+impl State {
+    pub fn join(&self, board_id: i32, addr: Addr<WsActor>) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.entry(board_id).or_default().push(Client {
+            addr,
+            participant: None,
+        });
+    }
+
+    // Returns the participant the client was, if no other connection to the board is them too
+    pub fn leave(&self, board_id: i32, addr: &Addr<WsActor>) -> Option<Participant> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let clients = rooms.get_mut(&board_id)?;
+        let position = clients.iter().position(|c| &c.addr == addr)?;
+        let participant = clients.remove(position).participant;
+        if clients.is_empty() {
+            rooms.remove(&board_id);
+        }
+        participant.filter(|p| !is_present(&rooms, board_id, &p.id))
+    }
+
+    // Records who the client is. Returns the other participants of the board, and whether they
+    // didn't know about this one yet (e.g. from another tab)
+    pub fn introduce(
+        &self,
+        board_id: i32,
+        addr: &Addr<WsActor>,
+        participant: Participant,
+    ) -> (Vec<Participant>, bool) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let is_new = !is_present(&rooms, board_id, &participant.id);
+        let clients = match rooms.get_mut(&board_id) {
+            Some(clients) => clients,
+            None => return (Vec::new(), false),
+        };
+        let mut others: Vec<Participant> = Vec::new();
+        for client in clients.iter_mut() {
+            if &client.addr == addr {
+                client.participant = Some(participant.clone());
+            } else if let Some(other) = &client.participant {
+                if other.id != participant.id && !others.iter().any(|p| p.id == other.id) {
+                    others.push(other.clone());
+                }
+            }
+        }
+        (others, is_new)
+    }
+
+    // Broadcast to all clients connected to `board_id` but `sender`, if given
+    pub fn broadcast(&self, board_id: i32, sender: Option<&Addr<WsActor>>, msg: &str) {
+        let rooms = self.rooms.lock().unwrap();
+        let clients = match rooms.get(&board_id) {
+            Some(clients) => clients,
+            None => return,
+        };
+        log::debug!(
+            "Received text: {}, broadcasting to {} clients on board {}",
+            msg,
+            clients.len(),
+            board_id
+        );
+        for client in clients.iter() {
+            if sender == Some(&client.addr) {
+                continue;
+            }
+            client.addr.do_send(Message(msg.to_string()));
+        }
+    }
+
+    // Disconnects the clients of the board who got their role from the invite
+    pub fn revoke_invite(&self, board_id: i32, invite_id: i32) {
+        let rooms = self.rooms.lock().unwrap();
+        if let Some(clients) = rooms.get(&board_id) {
+            for client in clients.iter() {
+                client.addr.do_send(InviteRevoked(invite_id));
+            }
+        }
+    }
+
+    // Disconnects the clients of the user on the board, once their role changed or they were
+    // removed. Those who still have access reconnect with their new role
+    pub fn change_member(&self, board_id: i32, user_id: i32) {
+        let rooms = self.rooms.lock().unwrap();
+        if let Some(clients) = rooms.get(&board_id) {
+            for client in clients.iter() {
+                client.addr.do_send(MemberChanged(user_id));
+            }
+        }
+    }
+
+    // Tells the clients of the board it was deleted, and disconnects them
+    pub fn close_room(&self, board_id: i32) {
+        let rooms = self.rooms.lock().unwrap();
+        if let Some(clients) = rooms.get(&board_id) {
+            log::info!("Closing {} sockets on board {}", clients.len(), board_id);
+            for client in clients.iter() {
+                client.addr.do_send(BoardDeleted);
+            }
+        }
+    }
+
+    pub fn room_size(&self, board_id: i32) -> usize {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(&board_id).map_or(0, |clients| clients.len())
     }
 }
 
+// Whether a client of the board said Hello as the participant `id`
+fn is_present(rooms: &HashMap<i32, Vec<Client>>, board_id: i32, id: &str) -> bool {
+    rooms.get(&board_id).is_some_and(|clients| {
+        clients
+            .iter()
+            .any(|c| c.participant.as_ref().is_some_and(|p| p.id == id))
+    })
+}
+
 #[derive(ActixMessage)]
 #[rtype(result = "()")]
 pub struct Message(pub String);
 
+#[derive(ActixMessage)]
+#[rtype(result = "()")]
+pub struct BoardDeleted;
+
+#[derive(ActixMessage)]
+#[rtype(result = "()")]
+pub struct InviteRevoked(pub i32);
+
+#[derive(ActixMessage)]
+#[rtype(result = "()")]
+pub struct MemberChanged(pub i32);
+
 pub struct WsActor {
     board_id: i32,
+    // Of the user on the board when they connected
+    role: Role,
+    // The invite given when connecting, if the role comes from it rather than from a membership
+    invite_id: Option<i32>,
+    // None for guests who joined with an invite
+    user_id: Option<i32>,
+    username: Option<String>,
+    // Given to its pointer and presence whatever the client says, so that nobody can move
+    // someone else's cursor, see participant_id
+    participant_id: String,
+    // When we last heard from the client
+    last_heartbeat: Instant,
+    // Embedded boards only watch, see EmbedQuery
+    embed: bool,
     state: Arc<State>,
     db_state: Arc<db::State>,
 }
 
-// Broadcast to all clients but ourselves
-fn broadcast(state: &State, ctx: &mut <WsActor as Actor>::Context, msg: &str) {
-    let clients = state.clients.lock().unwrap();
-    log::debug!(
-        "Received text: {}, broadcasting to {} clients",
-        msg,
-        clients.len()
-    );
-    for client in clients.iter() {
-        if *client == ctx.address() {
-            continue;
+impl WsActor {
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Heartbeat { interval, timeout } = self.state.heartbeat;
+        ctx.run_interval(interval, move |act, ctx| {
+            if act.last_heartbeat.elapsed() > timeout {
+                log::info!(
+                    "Disconnecting unresponsive client {:?} from board {}",
+                    ctx.address(),
+                    act.board_id
+                );
+                // Removes it from its room, see stopped()
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    // Tells the client why, and disconnects it
+    fn close_for_access(&self, ctx: &mut ws::WebsocketContext<Self>, reason: &str) {
+        let text = ServerMessage::AccessChanged(reason.to_string());
+        ctx.text(serde_json::to_string(&text).unwrap());
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(reason.to_string()),
+        }));
+        ctx.stop();
+    }
+
+    // Signed in users can't pass for someone else, guests name themselves
+    fn display_name(&self, name: String) -> String {
+        match &self.username {
+            Some(username) => username.clone(),
+            None => name.chars().take(MAX_USERNAME_LENGTH).collect(),
         }
-        client.do_send(Message(msg.to_string()));
     }
 }
 
-async fn parse_and_persist(client: db::Client, msg: &str, board_id: i32) {
-    // Parse and decide if needs to be persisted
-    let m: SocketMessage = serde_json::from_str(msg).unwrap();
-    match m {
-        SocketMessage::Circle(circle) => {
-            log::info!("Persisting circle for {:?}", board_id);
-            db::create_shape(&client, Shape::Circle(circle), board_id)
-                .await
-                .unwrap();
+// Answers the client after the messages already queued for it, like the events of its previous
+// operations, so it gets answers in the order it sent its operations
+fn reply(ctx: &mut ws::WebsocketContext<WsActor>, m: &ServerMessage) {
+    ctx.address()
+        .do_send(Message(serde_json::to_string(m).unwrap()));
+}
+
+// Applies a shape operation and records it as the next event of the board. Returns None for
+// messages that aren't persisted and operations that changed nothing, like one already applied
+// before a reconnection
+async fn persist(
+    store: Arc<dyn db::BoardStore>,
+    op_id: Option<OperationId>,
+    m: SocketMessage,
+    board_id: i32,
+) -> Result<Option<BoardEvent>, db::MyError> {
+    // Like the shapes of imported archives, as they may not come from our frontend
+    if let SocketMessage::ShapeCreated(entry) | SocketMessage::ShapeUpdated(entry) = &m {
+        entry.shape.validate().map_err(db::MyError::BadRequest)?;
+    }
+    if let SocketMessage::Pointer(_) | SocketMessage::Hello(_) = &m {
+        return Ok(None);
+    }
+    log::info!("Persisting {:?} on board {}", m, board_id);
+    match store.apply_and_record(board_id, op_id, &m).await {
+        Ok(Some(event)) => event.try_into().map(Some),
+        Ok(None) => {
+            log::debug!(
+                "Not recording {:?} on board {}, it changed nothing",
+                m,
+                board_id
+            );
+            Ok(None)
         }
-        SocketMessage::Pointer(_) => {
-            // pass
+        Err(e) => {
+            log::error!("Error persisting {:?} on board {}: {}", m, board_id, e);
+            Err(e)
         }
     }
 }
 
 impl Actor for WsActor {
     type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        log::debug!("Client {:?} joined board {}", ctx.address(), self.board_id);
+        self.state.join(self.board_id, ctx.address());
+        self.start_heartbeat(ctx);
+    }
+
+    // Called whether the socket was closed by the client or the actor was stopped
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        log::debug!("Client {:?} left board {}", ctx.address(), self.board_id);
+        if let Some(participant) = self.state.leave(self.board_id, &ctx.address()) {
+            let text = serde_json::to_string(&ServerMessage::Left(participant.id)).unwrap();
+            self.state.broadcast(self.board_id, None, &text);
+        }
+    }
 }
 
 impl Handler<Message> for WsActor {
@@ -71,66 +311,226 @@ impl Handler<Message> for WsActor {
     }
 }
 
-impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsActor {
-    fn started(&mut self, ctx: &mut Self::Context) {
-        println!("started: {:p} {:?}", self, ctx.address());
-        self.state
-            .as_ref()
-            .clients
-            .lock()
-            .unwrap()
-            .push(ctx.address());
+impl Handler<BoardDeleted> for WsActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: BoardDeleted, ctx: &mut Self::Context) {
+        ctx.text(serde_json::to_string(&ServerMessage::BoardDeleted).unwrap());
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Normal,
+            description: Some("Board deleted".to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+impl Handler<InviteRevoked> for WsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: InviteRevoked, ctx: &mut Self::Context) {
+        if self.invite_id == Some(msg.0) {
+            self.close_for_access(ctx, "This invite link was revoked");
+        }
     }
+}
 
-    fn finished(&mut self, ctx: &mut Self::Context) {
-        println!("finished: {:?}", ctx.address());
-        let mut clients = self.state.as_ref().clients.lock().unwrap();
-        let index = clients.iter().position(|a| *a == ctx.address()).unwrap();
-        clients.swap_remove(index);
+impl Handler<MemberChanged> for WsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: MemberChanged, ctx: &mut Self::Context) {
+        if self.user_id == Some(msg.0) {
+            self.close_for_access(ctx, "Your access to this board changed");
+        }
     }
+}
 
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsActor {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        // TODO: Implement heartbeat ?
-        // https://agmprojects.com/blog/building-a-rest-and-web-socket-api-with-actix.html
+        if msg.is_ok() {
+            self.last_heartbeat = Instant::now();
+        }
         match msg {
-            Ok(ws::Message::Ping(msg)) => {
-                println!("ping");
-                ctx.pong(&msg)
-            }
-            Ok(ws::Message::Pong(_)) => {
-                println!("pong");
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Pong(_)) => {}
+            Ok(ws::Message::Text(text)) if self.embed => {
+                log::debug!(
+                    "Ignoring message from embedded board {}: {}",
+                    self.board_id,
+                    text
+                );
             }
             Ok(ws::Message::Text(text)) => {
-                let text2 = text.clone();
-                let pool = self.db_state.pool.clone();
-                let board_id = self.board_id;
-                let fut = async move {
-                    let client = pool.get().await.unwrap();
-                    parse_and_persist(client, &text, board_id).await;
+                // Parse and decide if needs to be persisted
+                let m: SocketMessage = match serde_json::from_str(&text) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        log::warn!("Ignoring invalid message {:?}: {}", text, e);
+                        return;
+                    }
+                };
+                if let SocketMessage::Pointer(mut pointer) = m {
+                    pointer.id = self.participant_id.clone();
+                    pointer.name = self.display_name(pointer.name);
+                    let text = serde_json::to_string(&ServerMessage::Pointer(pointer)).unwrap();
+                    self.state
+                        .broadcast(self.board_id, Some(&ctx.address()), &text);
+                    return;
+                }
+                if let SocketMessage::Hello(mut participant) = m {
+                    participant.id = self.participant_id.clone();
+                    participant.name = self.display_name(participant.name);
+                    let (others, is_new) =
+                        self.state
+                            .introduce(self.board_id, &ctx.address(), participant.clone());
+                    ctx.text(
+                        serde_json::to_string(&ServerMessage::PresenceSnapshot(others)).unwrap(),
+                    );
+                    if is_new {
+                        let text =
+                            serde_json::to_string(&ServerMessage::Joined(participant)).unwrap();
+                        self.state
+                            .broadcast(self.board_id, Some(&ctx.address()), &text);
+                    }
+                    return;
+                }
+                if self.role < Role::Editor {
+                    let reason = "Viewers can't draw on this board".to_string();
+                    reply(ctx, &ServerMessage::Rejected(reason));
+                    return;
+                }
+                // Older clients send their operations without an id
+                let (op_id, m) = match m {
+                    SocketMessage::Operation(op) => (Some(op.id), *op.message),
+                    m => (None, m),
                 };
-                let fut = actix::fut::wrap_future::<_, Self>(fut);
-                ctx.spawn(fut);
-                broadcast(self.state.as_ref(), ctx, &text2);
+                let store = self.db_state.store.clone();
+                let board_id = self.board_id;
+                // Hold off the next messages until this one is persisted, so the operations of a
+                // client are sequenced in the order it sent them
+                let fut = actix::fut::wrap_future::<_, Self>(persist(store, op_id, m, board_id))
+                    .map(|result, act, ctx| match result {
+                        Ok(event) => {
+                            if let Some(event) = event {
+                                let text =
+                                    serde_json::to_string(&ServerMessage::Event(event)).unwrap();
+                                // The sender gets it too, to learn the sequence number
+                                act.state.broadcast(act.board_id, None, &text);
+                            }
+                            reply(ctx, &ServerMessage::Ack);
+                        }
+                        Err(e) => {
+                            // Details of server errors are logged by persist, not sent
+                            let reason = match e {
+                                db::MyError::NotFound => {
+                                    "The shape was deleted in the meantime".to_string()
+                                }
+                                db::MyError::BadRequest(message) => message,
+                                _ => "Your change couldn't be saved".to_string(),
+                            };
+                            reply(ctx, &ServerMessage::Rejected(reason));
+                        }
+                    });
+                ctx.wait(fut);
             }
             Ok(ws::Message::Binary(bin)) => ctx.binary(bin),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(e) => {
+                log::warn!("Websocket error on board {}: {}", self.board_id, e);
+                ctx.stop();
+            }
             _ => (),
         }
     }
 }
 
+// The tabs of a signed in user are a single participant. Guests can't be told apart, so each of
+// their connections is one
+fn participant_id(user: Option<&db::models::User>) -> String {
+    match user {
+        Some(user) => format!("user-{}", user.id),
+        None => format!("guest-{}", &auth::new_token()[..16]),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EmbedQuery {
+    // Set by boards shown in other sites. They are read-only whatever their invite allows, as
+    // its token ends up in a public page
+    #[serde(default)]
+    embed: bool,
+}
+
+// Browsers send the session cookie with websockets opened by any site, so they must come from our
+// own pages. Clients other than browsers don't send an Origin
+fn check_origin(req: &HttpRequest) -> Result<(), db::MyError> {
+    let origin = match req.headers().get(header::ORIGIN) {
+        Some(origin) => origin,
+        None => return Ok(()),
+    };
+    let host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, host)| host);
+    if host == Some(req.connection_info().host()) {
+        return Ok(());
+    }
+    log::warn!("Refusing websocket from origin {:?}", origin);
+    Err(db::MyError::Forbidden(
+        "Boards can only be opened from this site".to_string(),
+    ))
+}
+
 #[get("/boards/{id}/ws")]
 pub async fn ws_for_board(
     ws_data: web::Data<State>,
     db_data: web::Data<db::State>,
+    user: Option<CurrentUser>,
+    invite: web::Query<InviteQuery>,
     path: web::Path<(i32,)>,
     req: HttpRequest,
     stream: web::Payload,
 ) -> Result<HttpResponse, Error> {
-    println!("New websocket connection");
     let board_id = path.0;
+    log::debug!("New websocket connection to board {}", board_id);
+    check_origin(&req)?;
+    let embed = web::Query::<EmbedQuery>::from_query(req.query_string())?;
+    // Refuse the upgrade rather than accepting operations for a board that doesn't exist
+    let board = db_data.get_active_board(board_id).await?;
+    let user = user.as_ref().map(|u| &u.0);
+    let mut role = db_data
+        .check_access(&board, user, invite.token(), Role::Viewer)
+        .await?;
+    if embed.embed {
+        role = Role::Viewer;
+    }
+    let member_role = match user {
+        Some(user) => db_data.member_role(&board, user).await?,
+        None => None,
+    };
+    // Revoking the invite only matters to those who don't have its role as members
+    let invite_id = match invite.token() {
+        Some(token) => db_data
+            .get_valid_invite(token)
+            .await
+            .ok()
+            .filter(|i| i.board_id == board.id && Some(i.role()) > member_role)
+            .map(|i| i.id),
+        None => None,
+    };
     let resp = ws::start(
         WsActor {
             board_id,
+            role,
+            invite_id,
+            user_id: user.map(|u| u.id),
+            username: user.map(|u| u.username.clone()),
+            participant_id: participant_id(user),
+            last_heartbeat: Instant::now(),
+            embed: embed.embed,
             state: ws_data.deref().clone(),
             db_state: db_data.deref().clone(),
         },
@@ -0,0 +1,87 @@
+// Needs a Postgres server: LIVEBOARD_TEST_DATABASE_URL, e.g. postgres://postgres@localhost/postgres,
+// is used to create a scratch database. Skipped when it is not set
+use backend::config::{Args, Config, FileConfig};
+use backend::db;
+use shared::datatypes::{Circle, Color, Shape, SocketMessage};
+use tokio_postgres::NoTls;
+
+const SCRATCH_DB: &str = "liveboard_migrations_test";
+
+async fn connect(config: &tokio_postgres::Config) -> tokio_postgres::Client {
+    let (client, connection) = config.connect(NoTls).await.unwrap();
+    actix_web::rt::spawn(connection);
+    client
+}
+
+#[actix_web::test]
+async fn migrations_upgrade_the_baseline_schema() {
+    let url = match std::env::var("LIVEBOARD_TEST_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("LIVEBOARD_TEST_DATABASE_URL is not set, skipping");
+            return;
+        }
+    };
+    let args = Args {
+        database_url: Some(url),
+        ..Args::default()
+    };
+    let mut config = Config::merge(FileConfig::default(), &args).unwrap();
+    let admin = connect(&config.database_url).await;
+    let drop_scratch = format!("DROP DATABASE IF EXISTS {} WITH (FORCE);", SCRATCH_DB);
+    admin.batch_execute(&drop_scratch).await.unwrap();
+    admin
+        .batch_execute(&format!("CREATE DATABASE {};", SCRATCH_DB))
+        .await
+        .unwrap();
+    config.database_url.dbname(SCRATCH_DB);
+
+    // A deployment from before migrations, with a board drawn on
+    let old = connect(&config.database_url).await;
+    old.batch_execute(include_str!("../migrations/0001_initial.sql"))
+        .await
+        .unwrap();
+    let board_id: i32 = old
+        .query_one(
+            "INSERT INTO boards (name) VALUES ('old') RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    let shape = Shape::Circle(Circle {
+        x: 1.0,
+        y: 2.0,
+        radius: 3.0,
+        color: Color { r: 1, g: 2, b: 3 },
+    });
+    for _ in 0..2 {
+        old.execute(
+            "INSERT INTO shapes (board_id, shape) VALUES ($1, $2);",
+            &[&board_id, &serde_json::to_string(&shape).unwrap()],
+        )
+        .await
+        .unwrap();
+    }
+    drop(old);
+
+    let db_state = db::make_state(&config).unwrap();
+    db_state.store.migrate().await.unwrap();
+    // Nothing left to apply
+    db_state.store.migrate().await.unwrap();
+
+    let shapes = db_state.store.get_shapes(board_id).await.unwrap();
+    assert_eq!(shapes.len(), 2);
+    assert_ne!(shapes[0].uuid, shapes[1].uuid, "shapes got distinct ids");
+    let m = SocketMessage::ShapeDeleted(shapes[0].uuid);
+    let event = db_state
+        .store
+        .apply_and_record(board_id, None, &m)
+        .await
+        .unwrap();
+    assert_eq!(event.unwrap().seq, 1);
+    assert_eq!(db_state.store.get_shapes(board_id).await.unwrap().len(), 1);
+
+    drop(db_state);
+    admin.batch_execute(&drop_scratch).await.unwrap();
+}
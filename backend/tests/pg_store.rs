@@ -0,0 +1,164 @@
+// Needs a Postgres server: LIVEBOARD_TEST_DATABASE_URL, e.g. postgres://postgres@localhost/postgres,
+// is used to create scratch databases. Skipped when it is not set
+use backend::config::{Args, Config, FileConfig};
+use backend::db;
+use shared::datatypes::{
+    BoardEvent, BoardSort, Circle, Color, Shape, ShapeEntry, SocketMessage, Uuid,
+};
+use tokio_postgres::NoTls;
+
+// A migrated scratch database, one per test as they run in parallel
+struct Scratch {
+    db_state: db::State,
+    admin: tokio_postgres::Client,
+    drop_statement: String,
+}
+
+impl Scratch {
+    // None when LIVEBOARD_TEST_DATABASE_URL is not set
+    async fn create(name: &str) -> Option<Scratch> {
+        let url = match std::env::var("LIVEBOARD_TEST_DATABASE_URL") {
+            Ok(url) => url,
+            Err(_) => {
+                eprintln!("LIVEBOARD_TEST_DATABASE_URL is not set, skipping");
+                return None;
+            }
+        };
+        let args = Args {
+            database_url: Some(url),
+            ..Args::default()
+        };
+        let mut config = Config::merge(FileConfig::default(), &args).unwrap();
+        let (admin, connection) = config.database_url.connect(NoTls).await.unwrap();
+        actix_web::rt::spawn(connection);
+        let drop_statement = format!("DROP DATABASE IF EXISTS {} WITH (FORCE);", name);
+        admin.batch_execute(&drop_statement).await.unwrap();
+        admin
+            .batch_execute(&format!("CREATE DATABASE {};", name))
+            .await
+            .unwrap();
+        config.database_url.dbname(name);
+        let db_state = db::make_state(&config).unwrap();
+        db_state.store.migrate().await.unwrap();
+        Some(Scratch {
+            db_state,
+            admin,
+            drop_statement,
+        })
+    }
+
+    async fn drop(self) {
+        drop(self.db_state);
+        self.admin
+            .batch_execute(&self.drop_statement)
+            .await
+            .unwrap();
+    }
+}
+
+fn circle(x: f64) -> ShapeEntry {
+    ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x,
+            y: 0.0,
+            radius: 1.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    }
+}
+
+#[actix_web::test]
+async fn concurrent_operations_are_recorded_in_the_order_they_are_applied() {
+    let scratch = match Scratch::create("liveboard_pg_store_events_test").await {
+        Some(scratch) => scratch,
+        None => return,
+    };
+    let store = &scratch.db_state.store;
+    let user = store
+        .create_user("alice".to_string(), String::new())
+        .await
+        .unwrap();
+    let board = store
+        .create_board("Busy".to_string(), user.id)
+        .await
+        .unwrap();
+    let created = SocketMessage::ShapeCreated(circle(0.0));
+    store
+        .apply_and_record(board.id, None, &created)
+        .await
+        .unwrap();
+
+    let updates = (1..=20).map(|i| {
+        let store = store.clone();
+        let op_id = Some(Uuid::from_u128(i));
+        let message = SocketMessage::ShapeUpdated(circle(i as f64));
+        actix_web::rt::spawn(async move { store.apply_and_record(board.id, op_id, &message).await })
+    });
+    for update in updates.collect::<Vec<_>>() {
+        update.await.unwrap().unwrap();
+    }
+    // Sent again after a reconnection
+    let replayed = SocketMessage::ShapeUpdated(circle(1.0));
+    let op_id = Some(Uuid::from_u128(1));
+    let event = store.apply_and_record(board.id, op_id, &replayed);
+    assert!(event.await.unwrap().is_none());
+
+    let events = store.get_events(board.id, 0).await.unwrap();
+    let seqs: Vec<i64> = events.iter().map(|e| e.seq).collect();
+    assert_eq!(seqs, (1..=21).collect::<Vec<_>>());
+    // Replaying the events gives the stored shape
+    let last: BoardEvent = events.last().unwrap().clone().try_into().unwrap();
+    let shapes = store.get_shapes(board.id).await.unwrap();
+    let stored: Shape = serde_json::from_str(&shapes[0].shape).unwrap();
+    match last.message {
+        SocketMessage::ShapeUpdated(entry) => assert_eq!(entry.shape, stored),
+        m => panic!("unexpected last event {:?}", m),
+    }
+
+    scratch.drop().await;
+}
+
+#[actix_web::test]
+async fn boards_are_paged_in_name_order() {
+    let scratch = match Scratch::create("liveboard_pg_store_boards_test").await {
+        Some(scratch) => scratch,
+        None => return,
+    };
+    let store = &scratch.db_state.store;
+    let user = store
+        .create_user("alice".to_string(), String::new())
+        .await
+        .unwrap();
+    // Depending on the collation, the database may not lowercase them like Rust does
+    for name in ["Émile", "zebra", "Éclair", "Apple"] {
+        store.create_board(name.to_string(), user.id).await.unwrap();
+    }
+    let mut query = db::BoardQuery {
+        user_id: user.id,
+        archived: false,
+        search: None,
+        sort: BoardSort::Name,
+        after: None,
+        limit: 4,
+    };
+    let all = store.get_boards(&query).await.unwrap();
+    assert_eq!(all.len(), 4);
+
+    query.limit = 1;
+    let mut paged = vec![];
+    loop {
+        let page = store.get_boards(&query).await.unwrap();
+        match page.last() {
+            Some(last) => query.after = Some(last.into()),
+            None => break,
+        }
+        paged.extend(page);
+    }
+    let names = |boards: &[db::models::Board]| -> Vec<String> {
+        boards.iter().map(|b| b.name.clone()).collect()
+    };
+    assert_eq!(names(&paged), names(&all));
+
+    scratch.drop().await;
+}
@@ -0,0 +1,999 @@
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use backend::{auth, db, rest_handlers, thumbnails, ws_handlers};
+use shared::datatypes::{
+    Board, BoardArchive, BoardDetails, BoardPage, BoardShapes, Circle, Color, CreateBoardRequest,
+    CreateInviteRequest, Credentials, ErrorResponse, Invite, InviteTarget, Member, NaiveDateTime,
+    Role, SetMemberRequest, Shape, ShapeEntry, SocketMessage, UpdateBoardRequest, User, Uuid,
+    BOARD_ARCHIVE_VERSION,
+};
+
+fn memory_state() -> web::Data<db::State> {
+    web::Data::new(db::State::new(db::memory::MemoryStore::new()))
+}
+
+// Creates a user with a session, skipping the slow password hashing
+async fn sign_up(db_state: &db::State, username: &str) -> (i32, Cookie<'static>) {
+    let user = db_state
+        .store
+        .create_user(username.to_string(), "hash".to_string())
+        .await
+        .unwrap();
+    let token = format!("{}-token", username);
+    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+    db_state
+        .store
+        .create_session(token.clone(), user.id, expires_at)
+        .await
+        .unwrap();
+    (user.id, Cookie::new(auth::SESSION_COOKIE, token))
+}
+
+macro_rules! init_app {
+    ($db_state:expr) => {
+        test::init_service(
+            App::new()
+                .app_data($db_state.clone())
+                .app_data(web::Data::new(ws_handlers::make_state(
+                    ws_handlers::Heartbeat::default(),
+                )))
+                .app_data(web::Data::new(thumbnails::make_cache()))
+                .service(
+                    web::scope("/api")
+                        .configure(rest_handlers::extractor_errors)
+                        .service(auth::register)
+                        .service(auth::login)
+                        .service(auth::logout)
+                        .service(auth::me)
+                        .service(rest_handlers::create_board)
+                        .service(rest_handlers::list_boards)
+                        .service(rest_handlers::get_board)
+                        .service(rest_handlers::get_board_shapes)
+                        .service(rest_handlers::export_board_svg)
+                        .service(rest_handlers::get_board_thumbnail)
+                        .service(rest_handlers::export_board)
+                        .service(rest_handlers::import_board)
+                        .service(rest_handlers::update_board)
+                        .service(rest_handlers::delete_board)
+                        .service(rest_handlers::restore_board)
+                        .service(rest_handlers::get_board_events)
+                        .service(rest_handlers::list_members)
+                        .service(rest_handlers::set_member)
+                        .service(rest_handlers::remove_member)
+                        .service(rest_handlers::create_invite)
+                        .service(rest_handlers::list_invites)
+                        .service(rest_handlers::delete_invite)
+                        .service(rest_handlers::get_invite),
+                ),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn created_boards_are_listed() {
+    let db_state = memory_state();
+    let (_, cookie) = sign_up(&db_state, "alice").await;
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .uri("/api/boards")
+        .set_json(CreateBoardRequest {
+            name: "Roadmap".to_string(),
+        })
+        .to_request();
+    let created: Board = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(created.name, "Roadmap");
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards")
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.boards.len(), 1);
+    assert_eq!(page.boards[0].id, created.id);
+    assert!(page.next_cursor.is_none());
+}
+
+#[actix_web::test]
+async fn boards_are_listed_page_by_page() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    for name in ["Delta", "alpha", "Charlie", "bravo", "Alpaca"] {
+        db_state
+            .store
+            .create_board(name.to_string(), user_id)
+            .await
+            .unwrap();
+    }
+    let app = init_app!(db_state);
+
+    let mut names = vec![];
+    let mut uri = "/api/boards?sort=newest&limit=2".to_string();
+    loop {
+        let req = test::TestRequest::get()
+            .cookie(cookie.clone())
+            .uri(&uri)
+            .to_request();
+        let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+        assert!(page.boards.len() <= 2);
+        names.extend(page.boards.into_iter().map(|b| b.name));
+        match page.next_cursor {
+            Some(cursor) => uri = format!("/api/boards?sort=newest&limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(names, ["Alpaca", "bravo", "Charlie", "alpha", "Delta"]);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards?sort=name")
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<String> = page.boards.into_iter().map(|b| b.name).collect();
+    assert_eq!(names, ["Alpaca", "alpha", "bravo", "Charlie", "Delta"]);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards?query=ALP&sort=name&limit=1")
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.boards[0].name, "Alpaca");
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!(
+            "/api/boards?query=ALP&sort=name&limit=1&cursor={}",
+            page.next_cursor.unwrap()
+        ))
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.boards[0].name, "alpha");
+    assert!(page.next_cursor.is_none());
+
+    for uri in ["/api/boards?cursor=nonsense", "/api/boards?limit=0"] {
+        let req = test::TestRequest::get()
+            .cookie(cookie.clone())
+            .uri(uri)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[actix_web::test]
+async fn new_board_has_no_shapes_nor_events() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Empty".to_string(), user_id)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}", board.id))
+        .to_request();
+    let details: BoardDetails = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(details.board.name, "Empty");
+    assert_eq!(details.shape_count, 0);
+    assert_eq!(details.event_count, 0);
+    assert_eq!(details.last_modified, details.board.created_at);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}/shapes", board.id))
+        .to_request();
+    let shapes: BoardShapes = test::call_and_read_body_json(&app, req).await;
+    assert!(shapes.shapes.is_empty());
+    assert_eq!(shapes.last_seq, 0);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}/events?since=0", board.id))
+        .to_request();
+    let events: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(events.is_empty());
+
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    };
+    let message = SocketMessage::ShapeCreated(entry.clone());
+    db_state
+        .store
+        .apply_and_record(board.id, None, &message)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}/shapes", board.id))
+        .to_request();
+    let shapes: BoardShapes = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shapes.shapes.len(), 1);
+    assert_eq!(shapes.last_seq, 1);
+}
+
+#[actix_web::test]
+async fn errors_have_a_status_and_a_json_body() {
+    let db_state = memory_state();
+    let (_, cookie) = sign_up(&db_state, "alice").await;
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards/42")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(body.code, "not_found");
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .uri("/api/boards")
+        .set_json(CreateBoardRequest {
+            name: "  ".to_string(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(body.code, "bad_request");
+    assert!(body.message.contains("empty"), "{}", body.message);
+}
+
+#[actix_web::test]
+async fn malformed_requests_are_bad_requests() {
+    let db_state = memory_state();
+    let (_, cookie) = sign_up(&db_state, "alice").await;
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards/not-a-number")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .uri("/api/boards")
+        .insert_header(("content-type", "application/json"))
+        .set_payload("{\"nom\": 1}")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(body.code, "bad_request");
+}
+
+#[actix_web::test]
+async fn board_details_count_shapes_and_events() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Busy".to_string(), user_id)
+        .await
+        .unwrap();
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    };
+    let event = db_state
+        .store
+        .apply_and_record(board.id, None, &SocketMessage::ShapeCreated(entry))
+        .await
+        .unwrap()
+        .unwrap();
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}", board.id))
+        .to_request();
+    let details: BoardDetails = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(details.shape_count, 1);
+    assert_eq!(details.event_count, 1);
+    assert_eq!(details.last_modified, event.created_at);
+}
+
+#[actix_web::test]
+async fn boards_can_be_exported_as_svg() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Drawing".to_string(), user_id)
+        .await
+        .unwrap();
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    };
+    let message = SocketMessage::ShapeCreated(entry);
+    db_state
+        .store
+        .apply_and_record(board.id, None, &message)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}/export.svg", board.id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "image/svg+xml");
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("<circle cx=\"1\" cy=\"2\" r=\"3\" fill=\"#010203\"/>"));
+}
+
+#[actix_web::test]
+async fn thumbnails_are_rendered_until_the_board_changes() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Thumbnail".to_string(), user_id)
+        .await
+        .unwrap();
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 100.0,
+            y: 100.0,
+            radius: 50.0,
+            color: Color { r: 255, g: 0, b: 0 },
+        }),
+    };
+    let message = SocketMessage::ShapeCreated(entry);
+    db_state
+        .store
+        .apply_and_record(board.id, None, &message)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+    let uri = format!("/api/boards/{}/thumbnail.png", board.id);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&uri)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+    let etag = resp.headers().get("etag").unwrap().clone();
+    let png = test::read_body(resp).await;
+    let pixmap = tiny_skia::Pixmap::decode_png(&png).unwrap();
+    assert_eq!(
+        (pixmap.width(), pixmap.height()),
+        (thumbnails::WIDTH, thumbnails::HEIGHT)
+    );
+    // The circle is zoomed to fit, so it covers the center
+    let center = pixmap
+        .pixel(thumbnails::WIDTH / 2, thumbnails::HEIGHT / 2)
+        .unwrap();
+    assert_eq!((center.red(), center.green(), center.blue()), (255, 0, 0));
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&uri)
+        .insert_header(("if-none-match", etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    db_state
+        .store
+        .apply_and_record(
+            board.id,
+            None,
+            &SocketMessage::ShapeDeleted(Uuid::from_u128(1)),
+        )
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&uri)
+        .insert_header(("if-none-match", etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_ne!(resp.headers().get("etag").unwrap(), &etag);
+}
+
+#[actix_web::test]
+async fn exported_boards_can_be_imported() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Original".to_string(), user_id)
+        .await
+        .unwrap();
+    for i in 0..3 {
+        let entry = ShapeEntry {
+            id: Uuid::from_u128(i),
+            shape: Shape::Circle(Circle {
+                x: i as f64,
+                y: 0.0,
+                radius: 1.0,
+                color: Color { r: 1, g: 2, b: 3 },
+            }),
+        };
+        let message = SocketMessage::ShapeCreated(entry);
+        db_state
+            .store
+            .apply_and_record(board.id, None, &message)
+            .await
+            .unwrap();
+    }
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}/export", board.id))
+        .to_request();
+    let archive: BoardArchive = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(archive.version, BOARD_ARCHIVE_VERSION);
+    assert_eq!(archive.name, "Original");
+    assert_eq!(archive.shapes.len(), 3);
+
+    // Twice, shapes get new ids each time
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .cookie(cookie.clone())
+            .uri("/api/boards/import")
+            .set_json(&archive)
+            .to_request();
+        let imported: Board = test::call_and_read_body_json(&app, req).await;
+        assert_ne!(imported.id, board.id);
+        assert_eq!(imported.name, "Original");
+        let shapes = db_state.store.get_shapes(imported.id).await.unwrap();
+        let shapes: Vec<ShapeEntry> = shapes.into_iter().map(|s| s.try_into().unwrap()).collect();
+        let expected: Vec<Shape> = archive.shapes.iter().map(|e| e.shape.clone()).collect();
+        let actual: Vec<Shape> = shapes.iter().map(|e| e.shape.clone()).collect();
+        assert_eq!(actual, expected);
+        assert!(shapes.iter().all(|e| e.id.get_version_num() == 8));
+    }
+}
+
+#[actix_web::test]
+async fn invalid_archives_are_rejected() {
+    let db_state = memory_state();
+    let (_, cookie) = sign_up(&db_state, "alice").await;
+    let app = init_app!(db_state);
+    let valid = BoardArchive {
+        version: BOARD_ARCHIVE_VERSION,
+        name: "Imported".to_string(),
+        created_at: NaiveDateTime::from_timestamp(0, 0),
+        shapes: vec![ShapeEntry {
+            id: Uuid::from_u128(1),
+            shape: Shape::Circle(Circle {
+                x: 0.0,
+                y: 0.0,
+                radius: 1.0,
+                color: Color { r: 1, g: 2, b: 3 },
+            }),
+        }],
+    };
+    let mut future_version = valid.clone();
+    future_version.version += 1;
+    let mut invalid_shape = valid.clone();
+    invalid_shape.shapes[0].shape = Shape::Circle(Circle {
+        x: 0.0,
+        y: 0.0,
+        radius: -1.0,
+        color: Color { r: 1, g: 2, b: 3 },
+    });
+
+    for archive in [future_version, invalid_shape] {
+        let req = test::TestRequest::post()
+            .cookie(cookie.clone())
+            .uri("/api/boards/import")
+            .set_json(&archive)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .uri("/api/boards/import")
+        .set_payload(r#"{"version": 1, "name": "Broken", "shapes": [{"type": "Blob"}]}"#)
+        .insert_header(("content-type", "application/json"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards")
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    assert!(page.boards.is_empty());
+}
+
+#[actix_web::test]
+async fn boards_can_be_renamed() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Draft".to_string(), user_id)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+
+    let req = test::TestRequest::patch()
+        .cookie(cookie.clone())
+        .uri(&format!("/api/boards/{}", board.id))
+        .set_json(UpdateBoardRequest {
+            name: Some(" Final ".to_string()),
+        })
+        .to_request();
+    let renamed: Board = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(renamed.name, "Final");
+    assert_eq!(
+        db_state.store.get_board(board.id).await.unwrap().name,
+        "Final"
+    );
+}
+
+#[actix_web::test]
+async fn deleted_boards_are_archived_until_deleted_permanently() {
+    let db_state = memory_state();
+    let (user_id, cookie) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Old".to_string(), user_id)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+    let uri = format!("/api/boards/{}", board.id);
+
+    // Archived boards can't be deleted for good before being archived
+    let req = test::TestRequest::delete()
+        .cookie(cookie.clone())
+        .uri(&format!("{}?permanent=true", uri))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::delete()
+        .cookie(cookie.clone())
+        .uri(&uri)
+        .to_request();
+    let archived: Board = test::call_and_read_body_json(&app, req).await;
+    assert!(archived.archived);
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&uri)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri("/api/boards?archived=true")
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.boards.len(), 1);
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .uri(&format!("{}/restore", uri))
+        .to_request();
+    let restored: Board = test::call_and_read_body_json(&app, req).await;
+    assert!(!restored.archived);
+    let req = test::TestRequest::get()
+        .cookie(cookie.clone())
+        .uri(&uri)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    for req in [
+        test::TestRequest::delete().cookie(cookie.clone()).uri(&uri),
+        test::TestRequest::delete()
+            .cookie(cookie.clone())
+            .uri(&format!("{}?permanent=true", uri)),
+    ] {
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+    }
+    assert!(db_state.store.get_board(board.id).await.is_err());
+}
+
+#[actix_web::test]
+async fn users_register_sign_in_and_out() {
+    let db_state = memory_state();
+    let app = init_app!(db_state);
+    let credentials = |password: &str| Credentials {
+        username: "alice".to_string(),
+        password: password.to_string(),
+    };
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(credentials("short"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(credentials("correct horse"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let cookie = resp
+        .response()
+        .cookies()
+        .find(|c| c.name() == auth::SESSION_COOKIE)
+        .unwrap()
+        .into_owned();
+    assert!(cookie.http_only().unwrap());
+
+    // Usernames are unique
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(credentials("another password"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri("/api/auth/me")
+        .cookie(cookie.clone())
+        .to_request();
+    let me: User = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(me.username, "alice");
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/logout")
+        .cookie(cookie.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri("/api/auth/me")
+        .cookie(cookie)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(credentials("wrong password"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let wrong_password: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(wrong_password.code, "unauthorized");
+    // Doesn't tell whether the username exists
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(Credentials {
+            username: "bob".to_string(),
+            password: "correct horse".to_string(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let unknown_user: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(unknown_user.message, wrong_password.message);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(credentials("correct horse"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn boards_need_a_session() {
+    let db_state = memory_state();
+    let (user_id, _) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Private".to_string(), user_id)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+
+    for uri in [
+        "/api/boards".to_string(),
+        format!("/api/boards/{}", board.id),
+        format!("/api/boards/{}/shapes", board.id),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .cookie(Cookie::new(auth::SESSION_COOKIE, "forged"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn roles_limit_what_members_can_do() {
+    let db_state = memory_state();
+    let (owner_id, owner) = sign_up(&db_state, "alice").await;
+    let (_, viewer) = sign_up(&db_state, "bob").await;
+    let (_, stranger) = sign_up(&db_state, "mallory").await;
+    let board = db_state
+        .store
+        .create_board("Shared".to_string(), owner_id)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+    let uri = format!("/api/boards/{}", board.id);
+
+    // Boards are invisible to non-members
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .cookie(stranger.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get()
+        .uri("/api/boards")
+        .cookie(stranger.clone())
+        .to_request();
+    let page: BoardPage = test::call_and_read_body_json(&app, req).await;
+    assert!(page.boards.is_empty());
+
+    // Only the owner shares, and there is a single one
+    for (cookie, role, status) in [
+        (stranger.clone(), Role::Viewer, StatusCode::NOT_FOUND),
+        (owner.clone(), Role::Owner, StatusCode::BAD_REQUEST),
+        (owner.clone(), Role::Viewer, StatusCode::NO_CONTENT),
+    ] {
+        let req = test::TestRequest::put()
+            .uri(&format!("{}/members/bob", uri))
+            .cookie(cookie)
+            .set_json(SetMemberRequest { role })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status);
+    }
+    let req = test::TestRequest::put()
+        .uri(&format!("{}/members/nobody", uri))
+        .cookie(owner.clone())
+        .set_json(SetMemberRequest { role: Role::Editor })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .cookie(viewer.clone())
+        .to_request();
+    let details: BoardDetails = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(details.role, Role::Viewer);
+    let req = test::TestRequest::get()
+        .uri(&format!("{}/members", uri))
+        .cookie(viewer.clone())
+        .to_request();
+    let members: Vec<Member> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        members,
+        [
+            Member {
+                username: "alice".to_string(),
+                role: Role::Owner
+            },
+            Member {
+                username: "bob".to_string(),
+                role: Role::Viewer
+            },
+        ]
+    );
+
+    // Viewers can't manage the board
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .cookie(viewer.clone())
+        .set_json(UpdateBoardRequest {
+            name: Some("Mine".to_string()),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(body.code, "forbidden");
+    for req in [
+        test::TestRequest::delete().uri(&uri),
+        test::TestRequest::delete().uri(&format!("{}/members/alice", uri)),
+    ] {
+        let resp = test::call_service(&app, req.cookie(viewer.clone()).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    // But they can leave it
+    let req = test::TestRequest::delete()
+        .uri(&format!("{}/members/bob", uri))
+        .cookie(viewer.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .cookie(viewer)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn invites_open_boards_until_revoked() {
+    let db_state = memory_state();
+    let (owner_id, owner) = sign_up(&db_state, "alice").await;
+    let (_, member) = sign_up(&db_state, "bob").await;
+    let board = db_state
+        .store
+        .create_board("Invited".to_string(), owner_id)
+        .await
+        .unwrap();
+    let other = db_state
+        .store
+        .create_board("Other".to_string(), owner_id)
+        .await
+        .unwrap();
+    let app = init_app!(db_state);
+    let invites_uri = format!("/api/boards/{}/invites", board.id);
+
+    for (cookie, role, status) in [
+        (member.clone(), Role::Viewer, StatusCode::NOT_FOUND),
+        (owner.clone(), Role::Owner, StatusCode::BAD_REQUEST),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&invites_uri)
+            .cookie(cookie)
+            .set_json(CreateInviteRequest {
+                role,
+                expires_in_hours: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status);
+    }
+    let mut invites = vec![];
+    for role in [Role::Viewer, Role::Editor] {
+        let req = test::TestRequest::post()
+            .uri(&invites_uri)
+            .cookie(owner.clone())
+            .set_json(CreateInviteRequest {
+                role,
+                expires_in_hours: Some(24),
+            })
+            .to_request();
+        let invite: Invite = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(invite.role, role);
+        assert!(invite.expires_at.is_some());
+        invites.push(invite);
+    }
+    let req = test::TestRequest::get()
+        .uri(&invites_uri)
+        .cookie(owner.clone())
+        .to_request();
+    let listed: Vec<Invite> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(listed, invites);
+    let (viewer_invite, editor_invite) = (&invites[0], &invites[1]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/invites/{}", viewer_invite.token))
+        .to_request();
+    let target: InviteTarget = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        target,
+        InviteTarget {
+            board_id: board.id,
+            board_name: "Invited".to_string(),
+            role: Role::Viewer,
+        }
+    );
+
+    // Without signing in
+    let board_uri = |board_id: i32, invite: &Invite| {
+        format!("/api/boards/{}?invite={}", board_id, invite.token)
+    };
+    let req = test::TestRequest::get()
+        .uri(&board_uri(board.id, viewer_invite))
+        .to_request();
+    let details: BoardDetails = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(details.role, Role::Viewer);
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/boards/{}/shapes?invite={}",
+            board.id, viewer_invite.token
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let req = test::TestRequest::get()
+        .uri(&board_uri(other.id, viewer_invite))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Signed in users who aren't members get the role of the invite
+    let req = test::TestRequest::get()
+        .uri(&board_uri(board.id, editor_invite))
+        .cookie(member.clone())
+        .to_request();
+    let details: BoardDetails = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(details.role, Role::Editor);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("{}/{}", invites_uri, viewer_invite.id))
+        .cookie(owner.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&board_uri(board.id, viewer_invite))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/invites/{}", viewer_invite.token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn expired_invites_are_refused() {
+    let db_state = memory_state();
+    let (owner_id, _) = sign_up(&db_state, "alice").await;
+    let board = db_state
+        .store
+        .create_board("Invited".to_string(), owner_id)
+        .await
+        .unwrap();
+    let expired = db::models::Invite::new(
+        board.id,
+        "expired".to_string(),
+        Role::Editor,
+        Some(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)),
+    );
+    db_state.store.create_invite(expired).await.unwrap();
+    let app = init_app!(db_state);
+
+    for (uri, status) in [
+        (
+            format!("/api/boards/{}?invite=expired", board.id),
+            StatusCode::UNAUTHORIZED,
+        ),
+        ("/api/invites/expired".to_string(), StatusCode::NOT_FOUND),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", uri);
+    }
+}
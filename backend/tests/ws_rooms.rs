@@ -0,0 +1,867 @@
+use actix_web::{web, App, HttpServer};
+use backend::{auth, db, ws_handlers};
+use futures_util::{SinkExt, StreamExt};
+use shared::datatypes::{
+    Circle, Color, Operation, Participant, PointerPosition, Role, ServerMessage, Shape, ShapeEntry,
+    SocketMessage, Uuid, MAX_USERNAME_LENGTH,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::header;
+use tokio_tungstenite::tungstenite::Message;
+
+struct Fixture {
+    db_state: web::Data<db::State>,
+    // Two boards, owned by the user of `token`
+    boards: [i32; 2],
+    user_id: i32,
+    token: String,
+}
+
+async fn sign_up(db_state: &db::State, username: &str) -> (i32, String) {
+    let user = db_state
+        .store
+        .create_user(username.to_string(), "hash".to_string())
+        .await
+        .unwrap();
+    let token = format!("{}-token", username);
+    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+    db_state
+        .store
+        .create_session(token.clone(), user.id, expires_at)
+        .await
+        .unwrap();
+    (user.id, token)
+}
+
+async fn memory_state() -> Fixture {
+    let db_state = web::Data::new(db::State::new(db::memory::MemoryStore::new()));
+    let (user_id, token) = sign_up(&db_state, "alice").await;
+    let mut boards = [0; 2];
+    for (i, name) in ["first", "second"].into_iter().enumerate() {
+        boards[i] = db_state
+            .store
+            .create_board(name.to_string(), user_id)
+            .await
+            .unwrap()
+            .id;
+    }
+    Fixture {
+        db_state,
+        boards,
+        user_id,
+        token,
+    }
+}
+
+// Upgrade request for `url`, signed in with the session `token`
+fn ws_request(url: &str, token: &str) -> Request {
+    let mut request = url.into_client_request().unwrap();
+    let cookie = format!("{}={}", auth::SESSION_COOKIE, token);
+    request
+        .headers_mut()
+        .insert(header::COOKIE, cookie.parse().unwrap());
+    request
+}
+
+fn start_server(
+    ws_state: web::Data<ws_handlers::State>,
+    db_state: web::Data<db::State>,
+) -> SocketAddr {
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(ws_state.clone())
+            .app_data(db_state.clone())
+            .service(web::scope("/api").service(ws_handlers::ws_for_board))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    addr
+}
+
+async fn wait_for_room_size(ws_state: &ws_handlers::State, board_id: i32, size: usize) {
+    for _ in 0..100 {
+        if ws_state.room_size(board_id) == size {
+            return;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("board {} never reached {} clients", board_id, size);
+}
+
+fn pointer_position(id: &str, name: &str) -> PointerPosition {
+    PointerPosition {
+        id: id.to_string(),
+        name: name.to_string(),
+        x: 10.0,
+        y: 20.0,
+        color: Color { r: 1, g: 2, b: 3 },
+    }
+}
+
+// As the server names participants
+fn user_participant_id(user_id: i32) -> String {
+    format!("user-{}", user_id)
+}
+
+fn pointer_message(id: &str, name: &str) -> Message {
+    let m = SocketMessage::Pointer(pointer_position(id, name));
+    Message::Text(serde_json::to_string(&m).unwrap())
+}
+
+// As relayed to the others by the server, named after `name`
+fn pointer_broadcast(id: &str, name: &str) -> Message {
+    let m = ServerMessage::Pointer(pointer_position(id, name));
+    Message::Text(serde_json::to_string(&m).unwrap())
+}
+
+#[actix_web::test]
+async fn broadcast_stays_within_board() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let [first, second] = fixture.boards;
+    let addr = start_server(ws_state.clone(), fixture.db_state);
+    let url = |board_id: i32| format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut sender, _) = tokio_tungstenite::connect_async(ws_request(&url(first), &fixture.token))
+        .await
+        .unwrap();
+    let (mut same_board, _) =
+        tokio_tungstenite::connect_async(ws_request(&url(first), &fixture.token))
+            .await
+            .unwrap();
+    let (mut other_board, _) =
+        tokio_tungstenite::connect_async(ws_request(&url(second), &fixture.token))
+            .await
+            .unwrap();
+    wait_for_room_size(&ws_state, first, 2).await;
+    wait_for_room_size(&ws_state, second, 1).await;
+
+    sender
+        .send(pointer_message("sender", "alice"))
+        .await
+        .unwrap();
+
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), same_board.next())
+        .await
+        .expect("client on the same board should receive the broadcast")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        received,
+        pointer_broadcast(&user_participant_id(fixture.user_id), "alice")
+    );
+
+    let leaked = actix_web::rt::time::timeout(Duration::from_millis(200), other_board.next()).await;
+    assert!(
+        leaked.is_err(),
+        "client on another board received {:?}",
+        leaked
+    );
+
+    // The sender doesn't get its own message back either
+    let echoed = actix_web::rt::time::timeout(Duration::from_millis(200), sender.next()).await;
+    assert!(echoed.is_err(), "sender received {:?}", echoed);
+}
+
+#[actix_web::test]
+async fn disconnected_clients_leave_their_room() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let addr = start_server(ws_state.clone(), fixture.db_state);
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut client, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 1).await;
+
+    client.close(None).await.unwrap();
+    wait_for_room_size(&ws_state, board_id, 0).await;
+}
+
+#[actix_web::test]
+async fn unknown_boards_refuse_connections() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let addr = start_server(ws_state.clone(), fixture.db_state);
+    let url = format!("ws://{}/api/boards/{}/ws", addr, 42);
+
+    assert!(
+        tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+            .await
+            .is_err()
+    );
+    assert_eq!(ws_state.room_size(42), 0);
+}
+
+#[actix_web::test]
+async fn only_members_can_connect() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let (_, stranger) = sign_up(&fixture.db_state, "mallory").await;
+    let addr = start_server(ws_state.clone(), fixture.db_state);
+    let url = format!("ws://{}/api/boards/{}/ws", addr, fixture.boards[0]);
+
+    assert!(tokio_tungstenite::connect_async(&url).await.is_err());
+    assert!(
+        tokio_tungstenite::connect_async(ws_request(&url, "expired"))
+            .await
+            .is_err()
+    );
+    assert!(
+        tokio_tungstenite::connect_async(ws_request(&url, &stranger))
+            .await
+            .is_err()
+    );
+    assert_eq!(ws_state.room_size(fixture.boards[0]), 0);
+}
+
+#[actix_web::test]
+async fn other_sites_cannot_connect() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let addr = start_server(ws_state.clone(), fixture.db_state);
+    let url = format!("ws://{}/api/boards/{}/ws", addr, fixture.boards[0]);
+    let with_origin = |origin: &str| {
+        let mut request = ws_request(&url, &fixture.token);
+        request
+            .headers_mut()
+            .insert(header::ORIGIN, origin.parse().unwrap());
+        request
+    };
+
+    for origin in ["https://evil.example", "null"] {
+        let result = tokio_tungstenite::connect_async(with_origin(origin)).await;
+        assert!(result.is_err(), "{} connected", origin);
+    }
+    assert_eq!(ws_state.room_size(fixture.boards[0]), 0);
+    let own = format!("http://{}", addr);
+    tokio_tungstenite::connect_async(with_origin(&own))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, fixture.boards[0], 1).await;
+}
+
+#[actix_web::test]
+async fn viewers_can_point_but_not_draw() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let (viewer_id, viewer_token) = sign_up(&fixture.db_state, "bob").await;
+    fixture
+        .db_state
+        .store
+        .set_member(board_id, viewer_id, Role::Viewer)
+        .await
+        .unwrap();
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut owner, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    let (mut viewer, _) = tokio_tungstenite::connect_async(ws_request(&url, &viewer_token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+
+    let m = SocketMessage::ShapeDeleted(Uuid::from_u128(1));
+    let text = serde_json::to_string(&m).unwrap();
+    viewer.send(Message::Text(text)).await.unwrap();
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), viewer.next())
+        .await
+        .expect("viewer should be told its drawing was rejected")
+        .unwrap()
+        .unwrap();
+    match serde_json::from_str(received.to_text().unwrap()).unwrap() {
+        ServerMessage::Rejected(_) => {}
+        m => panic!("unexpected {:?}", m),
+    }
+
+    viewer.send(pointer_message("viewer", "bob")).await.unwrap();
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), owner.next())
+        .await
+        .expect("owner should see the viewer's pointer")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        received,
+        pointer_broadcast(&user_participant_id(viewer_id), "bob")
+    );
+    assert!(fixture
+        .db_state
+        .store
+        .get_events(board_id, 0)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_web::test]
+async fn shape_operations_are_persisted_and_sequenced() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let Fixture {
+        db_state,
+        boards,
+        token,
+        ..
+    } = memory_state().await;
+    let board = db_state.store.get_board(boards[0]).await.unwrap();
+    let addr = start_server(ws_state.clone(), db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board.id);
+
+    let (mut client, _) = tokio_tungstenite::connect_async(ws_request(&url, &token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board.id, 1).await;
+
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    };
+    for m in [
+        SocketMessage::ShapeCreated(entry.clone()),
+        // Replayed after a reconnection
+        SocketMessage::ShapeCreated(entry.clone()),
+        SocketMessage::ShapeDeleted(entry.id),
+    ] {
+        let text = serde_json::to_string(&m).unwrap();
+        client.send(Message::Text(text)).await.unwrap();
+    }
+
+    // The sender gets its own operations back, with their sequence number, and each operation
+    // acknowledged in order. The replay changes nothing so it isn't recorded
+    let mut received = vec![];
+    for _ in 0..5 {
+        received.push(match next_server_message(&mut client).await {
+            ServerMessage::Event(event) => Some(event.seq),
+            ServerMessage::Ack => None,
+            m => panic!("unexpected {:?}", m),
+        });
+    }
+    assert_eq!(received, [Some(1), None, None, Some(2), None]);
+    assert!(db_state
+        .store
+        .get_shapes(board.id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db_state.store.get_events(board.id, 0).await.unwrap().len(),
+        2
+    );
+}
+
+#[actix_web::test]
+async fn deleting_a_board_disconnects_its_clients() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let Fixture {
+        db_state,
+        boards,
+        token,
+        ..
+    } = memory_state().await;
+    let board_id = boards[0];
+    let addr = start_server(ws_state.clone(), db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut client, _) = tokio_tungstenite::connect_async(ws_request(&url, &token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 1).await;
+
+    // As done by rest_handlers::delete_board
+    db_state
+        .store
+        .set_board_archived(board_id, true)
+        .await
+        .unwrap();
+    ws_state.close_room(board_id);
+
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), client.next())
+        .await
+        .expect("client should be told the board was deleted")
+        .unwrap()
+        .unwrap();
+    match serde_json::from_str(received.to_text().unwrap()).unwrap() {
+        ServerMessage::BoardDeleted => {}
+        m => panic!("unexpected {:?}", m),
+    }
+    wait_for_room_size(&ws_state, board_id, 0).await;
+    // And can't reconnect
+    assert!(tokio_tungstenite::connect_async(ws_request(&url, &token))
+        .await
+        .is_err());
+}
+
+#[actix_web::test]
+async fn invites_open_sockets_until_revoked() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let invite = db::models::Invite::new(board_id, "edit-link".to_string(), Role::Editor, None);
+    let invite = fixture.db_state.store.create_invite(invite).await.unwrap();
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    // Without signing in
+    assert!(tokio_tungstenite::connect_async(&url).await.is_err());
+    let invite_url = format!("{}?invite={}", url, invite.token);
+    let (mut guest, _) = tokio_tungstenite::connect_async(&invite_url).await.unwrap();
+    let (mut owner, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+
+    // Editors draw
+    let m = SocketMessage::ShapeCreated(ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    });
+    let text = serde_json::to_string(&m).unwrap();
+    guest.send(Message::Text(text)).await.unwrap();
+    for client in [&mut guest, &mut owner] {
+        match next_server_message(client).await {
+            ServerMessage::Event(event) => assert_eq!(event.seq, 1),
+            m => panic!("unexpected {:?}", m),
+        }
+    }
+    assert!(matches!(
+        next_server_message(&mut guest).await,
+        ServerMessage::Ack
+    ));
+
+    // As done by rest_handlers::delete_invite
+    fixture
+        .db_state
+        .store
+        .delete_invite(board_id, invite.id)
+        .await
+        .unwrap();
+    ws_state.revoke_invite(board_id, invite.id);
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), guest.next())
+        .await
+        .expect("guest should be told the invite was revoked")
+        .unwrap()
+        .unwrap();
+    match serde_json::from_str(received.to_text().unwrap()).unwrap() {
+        ServerMessage::AccessChanged(_) => {}
+        m => panic!("unexpected {:?}", m),
+    }
+    // Members stay
+    wait_for_room_size(&ws_state, board_id, 1).await;
+    assert!(tokio_tungstenite::connect_async(&invite_url).await.is_err());
+}
+
+#[actix_web::test]
+async fn member_changes_disconnect_their_sockets() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let (bob_id, bob_token) = sign_up(&fixture.db_state, "bob").await;
+    let (carol_id, carol_token) = sign_up(&fixture.db_state, "carol").await;
+    for user_id in [bob_id, carol_id] {
+        fixture
+            .db_state
+            .store
+            .set_member(board_id, user_id, Role::Editor)
+            .await
+            .unwrap();
+    }
+    let invite = db::models::Invite::new(board_id, "view-link".to_string(), Role::Viewer, None);
+    let invite = fixture.db_state.store.create_invite(invite).await.unwrap();
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    // Carol's role comes from her membership, not from the invite she followed
+    let invite_url = format!("{}?invite={}", url, invite.token);
+    let (mut bob, _) = tokio_tungstenite::connect_async(ws_request(&url, &bob_token))
+        .await
+        .unwrap();
+    let (_carol, _) = tokio_tungstenite::connect_async(ws_request(&invite_url, &carol_token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+    fixture
+        .db_state
+        .store
+        .delete_invite(board_id, invite.id)
+        .await
+        .unwrap();
+    ws_state.revoke_invite(board_id, invite.id);
+
+    // As done by rest_handlers::set_member
+    fixture
+        .db_state
+        .store
+        .set_member(board_id, bob_id, Role::Viewer)
+        .await
+        .unwrap();
+    ws_state.change_member(board_id, bob_id);
+    match next_server_message(&mut bob).await {
+        ServerMessage::AccessChanged(_) => {}
+        m => panic!("unexpected {:?}", m),
+    }
+    wait_for_room_size(&ws_state, board_id, 1).await;
+
+    // Reconnecting applies the new role
+    let (mut bob, _) = tokio_tungstenite::connect_async(ws_request(&url, &bob_token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+    let m = SocketMessage::ShapeDeleted(Uuid::from_u128(1));
+    let text = serde_json::to_string(&m).unwrap();
+    bob.send(Message::Text(text)).await.unwrap();
+    match next_server_message(&mut bob).await {
+        ServerMessage::Rejected(_) => {}
+        m => panic!("unexpected {:?}", m),
+    }
+
+    // As done by rest_handlers::remove_member
+    fixture
+        .db_state
+        .store
+        .remove_member(board_id, bob_id)
+        .await
+        .unwrap();
+    ws_state.change_member(board_id, bob_id);
+    match next_server_message(&mut bob).await {
+        ServerMessage::AccessChanged(_) => {}
+        m => panic!("unexpected {:?}", m),
+    }
+    wait_for_room_size(&ws_state, board_id, 1).await;
+    assert!(
+        tokio_tungstenite::connect_async(ws_request(&url, &bob_token))
+            .await
+            .is_err()
+    );
+}
+
+#[actix_web::test]
+async fn pointers_are_named_after_their_sender() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let invite = db::models::Invite::new(board_id, "view-link".to_string(), Role::Viewer, None);
+    fixture.db_state.store.create_invite(invite).await.unwrap();
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut owner, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    let invite_url = format!("{}?invite=view-link", url);
+    let (mut guest, _) = tokio_tungstenite::connect_async(&invite_url).await.unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+
+    // Signed in users get their id and username whatever they send
+    let bob_id = user_participant_id(fixture.user_id + 100);
+    owner.send(pointer_message(&bob_id, "bob")).await.unwrap();
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), guest.next())
+        .await
+        .expect("guest should see the owner's pointer")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        received,
+        pointer_broadcast(&user_participant_id(fixture.user_id), "alice")
+    );
+
+    // Guests pick their name, within limits, but not their id
+    let long_name = "g".repeat(MAX_USERNAME_LENGTH + 10);
+    let alice_id = user_participant_id(fixture.user_id);
+    guest
+        .send(pointer_message(&alice_id, &long_name))
+        .await
+        .unwrap();
+    match next_server_message(&mut owner).await {
+        ServerMessage::Pointer(pointer) => {
+            assert!(pointer.id.starts_with("guest-"), "{}", pointer.id);
+            assert_eq!(pointer.name, long_name[..MAX_USERNAME_LENGTH]);
+        }
+        m => panic!("unexpected {:?}", m),
+    }
+}
+
+fn participant(id: &str, name: &str) -> Participant {
+    Participant {
+        id: id.to_string(),
+        name: name.to_string(),
+        color: Color { r: 1, g: 2, b: 3 },
+    }
+}
+
+fn hello_message(id: &str) -> Message {
+    let m = SocketMessage::Hello(participant(id, "someone"));
+    Message::Text(serde_json::to_string(&m).unwrap())
+}
+
+async fn next_server_message<S>(client: &mut S) -> ServerMessage
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let received = actix_web::rt::time::timeout(Duration::from_secs(2), client.next())
+        .await
+        .expect("a message should be received")
+        .unwrap()
+        .unwrap();
+    serde_json::from_str(received.to_text().unwrap()).unwrap()
+}
+
+#[actix_web::test]
+async fn participants_are_told_who_joins_and_leaves() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let (bob_id, bob_token) = sign_up(&fixture.db_state, "bob").await;
+    fixture
+        .db_state
+        .store
+        .set_member(board_id, bob_id, Role::Viewer)
+        .await
+        .unwrap();
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+    let connect = |token: String| {
+        let request = ws_request(&url, &token);
+        async move { tokio_tungstenite::connect_async(request).await.unwrap().0 }
+    };
+
+    let mut alice = connect(fixture.token.clone()).await;
+    alice.send(hello_message("alice-browser")).await.unwrap();
+    match next_server_message(&mut alice).await {
+        ServerMessage::PresenceSnapshot(others) => assert!(others.is_empty()),
+        m => panic!("unexpected {:?}", m),
+    }
+
+    // Ids are given by the server, so bob can't pass for alice
+    let (alice_id, bob_id) = (
+        user_participant_id(fixture.user_id),
+        user_participant_id(bob_id),
+    );
+    let mut bob = connect(bob_token).await;
+    bob.send(hello_message("alice-browser")).await.unwrap();
+    match next_server_message(&mut bob).await {
+        ServerMessage::PresenceSnapshot(others) => {
+            assert_eq!(others, vec![participant(&alice_id, "alice")])
+        }
+        m => panic!("unexpected {:?}", m),
+    }
+    match next_server_message(&mut alice).await {
+        ServerMessage::Joined(p) => assert_eq!(p, participant(&bob_id, "bob")),
+        m => panic!("unexpected {:?}", m),
+    }
+
+    // Another tab of the same user is the same participant
+    let mut alice_tab = connect(fixture.token.clone()).await;
+    alice_tab
+        .send(hello_message("alice-other-browser"))
+        .await
+        .unwrap();
+    match next_server_message(&mut alice_tab).await {
+        ServerMessage::PresenceSnapshot(others) => {
+            assert_eq!(others, vec![participant(&bob_id, "bob")])
+        }
+        m => panic!("unexpected {:?}", m),
+    }
+    alice_tab.close(None).await.unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+    let quiet = actix_web::rt::time::timeout(Duration::from_millis(200), bob.next()).await;
+    assert!(quiet.is_err(), "bob received {:?}", quiet);
+
+    alice.close(None).await.unwrap();
+    match next_server_message(&mut bob).await {
+        ServerMessage::Left(id) => assert_eq!(id, alice_id),
+        m => panic!("unexpected {:?}", m),
+    }
+}
+
+#[actix_web::test]
+async fn unresponsive_clients_are_disconnected() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat {
+        interval: Duration::from_millis(50),
+        timeout: Duration::from_millis(300),
+    }));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let addr = start_server(ws_state.clone(), fixture.db_state);
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut alive, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    // Never read from, so it never answers pings, like a peer that went away without closing
+    let (_silent, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+
+    // Reading lets the client answer pings
+    let mut pings = 0;
+    let deadline = std::time::Instant::now() + Duration::from_millis(600);
+    while std::time::Instant::now() < deadline {
+        let received = actix_web::rt::time::timeout(Duration::from_millis(100), alive.next()).await;
+        if let Ok(Some(Ok(Message::Ping(_)))) = received {
+            pings += 1;
+        }
+    }
+    assert!(pings > 0, "the server should ping its clients");
+    assert_eq!(ws_state.room_size(board_id), 1);
+}
+
+#[actix_web::test]
+async fn embedded_boards_cannot_draw() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let invite = db::models::Invite::new(board_id, "edit-link".to_string(), Role::Editor, None);
+    fixture.db_state.store.create_invite(invite).await.unwrap();
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+
+    let (mut owner, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+    let embed_url = format!("{}?invite=edit-link&embed=true", url);
+    let (mut embed, _) = tokio_tungstenite::connect_async(&embed_url).await.unwrap();
+    wait_for_room_size(&ws_state, board_id, 2).await;
+
+    // Even with an edit link
+    let m = SocketMessage::ShapeCreated(ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    });
+    embed
+        .send(Message::Text(serde_json::to_string(&m).unwrap()))
+        .await
+        .unwrap();
+    embed.send(pointer_message("embed", "guest")).await.unwrap();
+    let received = actix_web::rt::time::timeout(Duration::from_millis(300), owner.next()).await;
+    assert!(received.is_err(), "owner received {:?}", received);
+    let store = &fixture.db_state.store;
+    assert!(store.get_shapes(board_id).await.unwrap().is_empty());
+    assert!(store.get_events(board_id, 0).await.unwrap().is_empty());
+
+    // Still watching
+    let text = serde_json::to_string(&m).unwrap();
+    owner.send(Message::Text(text)).await.unwrap();
+    match next_server_message(&mut embed).await {
+        ServerMessage::Event(event) => assert_eq!(event.seq, 1),
+        m => panic!("unexpected {:?}", m),
+    }
+}
+
+#[actix_web::test]
+async fn invalid_shapes_are_rejected() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+    let (mut client, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: -3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    };
+    for m in [
+        SocketMessage::ShapeCreated(entry.clone()),
+        SocketMessage::ShapeUpdated(entry),
+    ] {
+        let text = serde_json::to_string(&m).unwrap();
+        client.send(Message::Text(text)).await.unwrap();
+        match next_server_message(&mut client).await {
+            ServerMessage::Rejected(reason) => assert!(reason.starts_with("Sizes"), "{}", reason),
+            m => panic!("unexpected {:?}", m),
+        }
+    }
+    let store = &fixture.db_state.store;
+    assert!(store.get_shapes(board_id).await.unwrap().is_empty());
+    assert!(store.get_events(board_id, 0).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn replayed_operations_are_applied_once() {
+    let ws_state = web::Data::new(ws_handlers::make_state(ws_handlers::Heartbeat::default()));
+    let fixture = memory_state().await;
+    let board_id = fixture.boards[0];
+    let addr = start_server(ws_state.clone(), fixture.db_state.clone());
+    let url = format!("ws://{}/api/boards/{}/ws", addr, board_id);
+    let (mut client, _) = tokio_tungstenite::connect_async(ws_request(&url, &fixture.token))
+        .await
+        .unwrap();
+
+    let circle = |x| ShapeEntry {
+        id: Uuid::from_u128(1),
+        shape: Shape::Circle(Circle {
+            x,
+            y: 2.0,
+            radius: 3.0,
+            color: Color { r: 1, g: 2, b: 3 },
+        }),
+    };
+    let operation = |id, message| {
+        let m = SocketMessage::Operation(Operation {
+            id: Uuid::from_u128(id),
+            message: Box::new(message),
+        });
+        Message::Text(serde_json::to_string(&m).unwrap())
+    };
+    for m in [
+        operation(10, SocketMessage::ShapeCreated(circle(1.0))),
+        operation(11, SocketMessage::ShapeUpdated(circle(2.0))),
+        // Someone else's newer edit
+        operation(20, SocketMessage::ShapeUpdated(circle(3.0))),
+        // Sent again after a reconnection, whose acknowledgement was lost
+        operation(11, SocketMessage::ShapeUpdated(circle(2.0))),
+    ] {
+        client.send(m).await.unwrap();
+    }
+
+    let mut received = vec![];
+    for _ in 0..7 {
+        received.push(match next_server_message(&mut client).await {
+            ServerMessage::Event(event) => Some(event.seq),
+            ServerMessage::Ack => None,
+            m => panic!("unexpected {:?}", m),
+        });
+    }
+    assert_eq!(
+        received,
+        [Some(1), None, Some(2), None, Some(3), None, None]
+    );
+    let store = &fixture.db_state.store;
+    assert_eq!(store.get_events(board_id, 0).await.unwrap().len(), 3);
+    let shapes = store.get_shapes(board_id).await.unwrap();
+    let stored: Shape = serde_json::from_str(&shapes[0].shape).unwrap();
+    assert_eq!(stored, circle(3.0).shape);
+}
@@ -1,5 +1,11 @@
-use gloo_net::http::Request;
-use shared::datatypes::{Board, CreateBoardRequest, Shape};
+use crate::utils::window;
+use gloo_net::http::{Request, Response};
+use serde::de::DeserializeOwned;
+use shared::datatypes::{
+    Board, BoardArchive, BoardDetails, BoardEvent, BoardPage, BoardShapes, BoardSort,
+    CreateBoardRequest, CreateInviteRequest, Credentials, ErrorResponse, Invite, InviteTarget,
+    Role, SetMemberRequest, UpdateBoardRequest, User,
+};
 use std::{
     error::Error,
     fmt::{self, Debug, Display, Formatter},
@@ -7,11 +13,13 @@ use std::{
 
 #[derive(Debug)]
 pub struct APIError {
+    // `ErrorResponse::code` if the backend returned an error, `network` or `http` otherwise
+    pub code: String,
     pub message: String,
 }
 impl Display for APIError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        Debug::fmt(&self.message, f)
+        Display::fmt(&self.message, f)
     }
 }
 impl Error for APIError {}
@@ -19,36 +27,216 @@ impl Error for APIError {}
 impl From<gloo_net::Error> for APIError {
     fn from(err: gloo_net::Error) -> APIError {
         APIError {
+            code: "network".to_string(),
             message: format!("gloo::Error : {:?}", err.to_string()),
         }
     }
 }
 
-pub async fn fetch_boards() -> Result<Vec<Board>, APIError> {
+// Decodes the body of successful responses as T
+async fn decode<T: DeserializeOwned>(resp: Response) -> Result<T, APIError> {
+    Ok(check(resp).await?.json::<T>().await?)
+}
+
+// Turns error responses into an APIError, from their ErrorResponse body. Requests made without a
+// session send to the login page
+async fn check(resp: Response) -> Result<Response, APIError> {
+    if resp.ok() {
+        return Ok(resp);
+    }
+    match resp.json::<ErrorResponse>().await {
+        Ok(err) if err.code == "unauthorized" && !resp.url().contains("/api/auth/") => {
+            window().location().set_href("/login").unwrap();
+            Err(APIError {
+                code: err.code,
+                message: err.message,
+            })
+        }
+        Ok(err) => Err(APIError {
+            code: err.code,
+            message: err.message,
+        }),
+        Err(_) => Err(APIError {
+            code: "http".to_string(),
+            message: format!("HTTP error {} {}", resp.status(), resp.status_text()),
+        }),
+    }
+}
+
+// `cursor` is the `next_cursor` of the previous page, if any
+pub async fn fetch_boards(
+    archived: bool,
+    query: &str,
+    sort: BoardSort,
+    cursor: Option<&str>,
+) -> Result<BoardPage, APIError> {
+    let sort = match sort {
+        BoardSort::Oldest => "oldest",
+        BoardSort::Newest => "newest",
+        BoardSort::Name => "name",
+    };
     let resp = Request::get("/api/boards")
+        .query([
+            ("archived", archived.to_string().as_str()),
+            ("query", query),
+            ("sort", sort),
+            ("cursor", cursor.unwrap_or_default()),
+        ])
         .send()
-        .await?
-        .json::<Vec<Board>>()
         .await?;
-    Ok(resp)
+    decode(resp).await
 }
 
 pub async fn create_board(name: String) -> Result<Board, APIError> {
     let req = CreateBoardRequest { name };
-    let resp = Request::post("/api/boards")
+    let resp = Request::post("/api/boards").json(&req)?.send().await?;
+    decode(resp).await
+}
+
+pub async fn import_board(archive: &BoardArchive) -> Result<Board, APIError> {
+    let resp = Request::post("/api/boards/import")
+        .json(archive)?
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn rename_board(board_id: i32, name: String) -> Result<Board, APIError> {
+    let req = UpdateBoardRequest { name: Some(name) };
+    let resp = Request::patch(&format!("/api/boards/{}", board_id))
+        .json(&req)?
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+// Archives the board, see delete_board_permanently
+pub async fn delete_board(board_id: i32) -> Result<Board, APIError> {
+    let resp = Request::delete(&format!("/api/boards/{}", board_id))
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn restore_board(board_id: i32) -> Result<Board, APIError> {
+    let resp = Request::post(&format!("/api/boards/{}/restore", board_id))
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn delete_board_permanently(board_id: i32) -> Result<(), APIError> {
+    let resp = Request::delete(&format!("/api/boards/{}", board_id))
+        .query([("permanent", "true")])
+        .send()
+        .await?;
+    check(resp).await?;
+    Ok(())
+}
+
+// Boards can be opened with an invite token instead of being a member
+fn with_invite(req: Request, invite: Option<&str>) -> Request {
+    match invite {
+        Some(token) => req.query([("invite", token)]),
+        None => req,
+    }
+}
+
+pub async fn fetch_board(board_id: i32, invite: Option<&str>) -> Result<BoardDetails, APIError> {
+    let req = Request::get(&format!("/api/boards/{}", board_id));
+    let resp = with_invite(req, invite).send().await?;
+    decode(resp).await
+}
+
+pub async fn fetch_shapes(board_id: i32, invite: Option<&str>) -> Result<BoardShapes, APIError> {
+    let req = Request::get(&format!("/api/boards/{}/shapes", board_id));
+    let resp = with_invite(req, invite).send().await?;
+    decode(resp).await
+}
+
+pub async fn fetch_events(
+    board_id: i32,
+    since: i64,
+    invite: Option<&str>,
+) -> Result<Vec<BoardEvent>, APIError> {
+    let req = Request::get(&format!("/api/boards/{}/events", board_id))
+        .query([("since", since.to_string())]);
+    let resp = with_invite(req, invite).send().await?;
+    decode(resp).await
+}
+
+pub async fn register(credentials: &Credentials) -> Result<User, APIError> {
+    let resp = Request::post("/api/auth/register")
+        .json(credentials)?
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn login(credentials: &Credentials) -> Result<User, APIError> {
+    let resp = Request::post("/api/auth/login")
+        .json(credentials)?
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn logout() -> Result<(), APIError> {
+    let resp = Request::post("/api/auth/logout").send().await?;
+    check(resp).await?;
+    Ok(())
+}
+
+pub async fn fetch_me() -> Result<User, APIError> {
+    let resp = Request::get("/api/auth/me").send().await?;
+    decode(resp).await
+}
+
+// Shares the board with `username`, or changes their role
+pub async fn set_member(board_id: i32, username: &str, role: Role) -> Result<(), APIError> {
+    let req = SetMemberRequest { role };
+    let resp = Request::put(&format!("/api/boards/{}/members/{}", board_id, username))
+        .json(&req)?
+        .send()
+        .await?;
+    check(resp).await?;
+    Ok(())
+}
+
+pub async fn fetch_invite(token: &str) -> Result<InviteTarget, APIError> {
+    let resp = Request::get(&format!("/api/invites/{}", token))
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn fetch_invites(board_id: i32) -> Result<Vec<Invite>, APIError> {
+    let resp = Request::get(&format!("/api/boards/{}/invites", board_id))
+        .send()
+        .await?;
+    decode(resp).await
+}
+
+pub async fn create_invite(
+    board_id: i32,
+    role: Role,
+    expires_in_hours: Option<i64>,
+) -> Result<Invite, APIError> {
+    let req = CreateInviteRequest {
+        role,
+        expires_in_hours,
+    };
+    let resp = Request::post(&format!("/api/boards/{}/invites", board_id))
         .json(&req)?
         .send()
-        .await?
-        .json::<Board>()
         .await?;
-    Ok(resp)
+    decode(resp).await
 }
 
-pub async fn fetch_shapes(board_id: i32) -> Result<Vec<Shape>, APIError> {
-    let resp = Request::get(&format!("/api/boards/{}", board_id))
+pub async fn delete_invite(board_id: i32, invite_id: i32) -> Result<(), APIError> {
+    let resp = Request::delete(&format!("/api/boards/{}/invites/{}", board_id, invite_id))
         .send()
-        .await?
-        .json::<Vec<Shape>>()
         .await?;
-    Ok(resp)
+    check(resp).await?;
+    Ok(())
 }
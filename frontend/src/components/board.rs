@@ -1,48 +1,162 @@
-use super::super::api::fetch_shapes;
-use super::ws_client::{new_ws_client, WSClient};
+use super::super::api::{
+    create_invite, delete_invite, fetch_board, fetch_events, fetch_invites, fetch_shapes, APIError,
+};
+use super::outbox::Outbox;
+use super::ws_client::{new_ws_client, ConnectionState, WSClient};
+use crate::drawing::draw_shape;
+use crate::identity::Identity;
 use crate::live_cursor::LiveCursor;
-use crate::utils::set_interval;
+use crate::routes::Route;
+use crate::utils::{new_shape_id, set_interval, window};
 use futures::SinkExt;
 use gloo_net::websocket::Message as WsMessage;
 use log;
-use shared::datatypes::{Circle, Color, PointerPosition, Shape, SocketMessage};
+use shared::datatypes::{
+    Arrow, BoardDetails, BoardEvent, BoardShapes, Circle, Ellipse, Invite, Line, Participant,
+    Point, PointerPosition, Rectangle, Role, ServerMessage, Shape, ShapeEntry, ShapeId,
+    SocketMessage, Stroke,
+};
+use shared::geometry::{Bounds, Viewport};
+use shared::history::{History, ShapeOperation};
 use std::collections::HashMap;
-use std::f64;
+use wasm_bindgen::convert::FromWasmAbi;
 use wasm_bindgen::prelude::Closure;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use web_sys::HtmlCanvasElement;
 use web_sys::Path2d;
-use yew::{html, html::Scope, Component, Context, Html, NodeRef, Properties};
+use yew::{html, html::Scope, Component, Context, Html, MouseEvent, NodeRef, Properties};
+use yew_router::prelude::{History as _, RouterScopeExt};
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tool {
+    Dot,
+    Rectangle,
+    Ellipse,
+    Line,
+    Arrow,
+    Pen,
+    Move,
+    Eraser,
+    Pan,
+}
+
+const TOOLS: [(Tool, &str); 9] = [
+    (Tool::Dot, "Dot"),
+    (Tool::Rectangle, "Rectangle"),
+    (Tool::Ellipse, "Ellipse"),
+    (Tool::Line, "Line"),
+    (Tool::Arrow, "Arrow"),
+    (Tool::Pen, "Pen"),
+    (Tool::Move, "Move"),
+    (Tool::Eraser, "Eraser"),
+    (Tool::Pan, "Hand"),
+];
+
+const STROKE_WIDTH: f64 = 2.0;
+// How far from a shape, in screen pixels, a click still selects it
+const HIT_TOLERANCE: f64 = 4.0;
+// Screen pixels left around the shapes by "zoom to fit"
+const FIT_MARGIN: f64 = 40.0;
+// Scale factor per pixel of wheel scrolling
+const WHEEL_ZOOM_SPEED: f64 = 0.002;
+
+// Mouse positions are in screen pixels, relative to the canvas
 #[derive(Debug)]
 pub enum Msg {
     Draw,
-    ButtonPressed,
+    ButtonPressed(i32, i32),
     ButtonReleased(i32, i32),
     MouseMove(i32, i32),
-    NewCircle(Circle),
+    PanStarted(i32, i32),
+    Zoom(i32, i32, f64),
+    ZoomToFit,
+    SelectTool(Tool),
+    Undo,
+    Redo,
+    EventReceived(BoardEvent),
     OtherPointerMoved(PointerPosition),
-    OnInitialShapesLoaded(Vec<Shape>),
-    OnLoadError,
+    // Who else is on the board, as told by the server
+    PresenceSnapshot(Vec<Participant>),
+    Joined(Participant),
+    Left(String),
+    ConnectionStateChanged(ConnectionState),
+    OnDetailsLoaded(BoardDetails),
+    OnShapesLoaded(BoardShapes),
+    OnEventsLoaded(Vec<BoardEvent>),
+    OnLoadError(APIError),
+    BoardDeleted,
+    // The server refused one of our operations
+    Rejected(String),
+    // The server handled one of our operations
+    Acknowledged,
+    // We'll reconnect with a new role, if we still have one
+    AccessChanged(String),
+    // Shows or hides the owner's list of invite links
+    ToggleInvites,
+    InvitesLoaded(Vec<Invite>),
+    CreateInvite(Role),
+    CopyInvite(String),
+    CopyEmbed(String),
+    RevokeInvite(i32),
+    InviteError(APIError),
 }
 
 pub struct Board {
     canvas_ref: NodeRef,
-    button_pressed: bool,
-    circles: Vec<Circle>,
+    tool: Tool,
+    viewport: Viewport,
+    // Last screen position of the mouse while panning
+    panning: Option<Point>,
+    // Where the current drag started (last position for the move tool), if the button is pressed.
+    // Shapes and drag positions are in world coordinates
+    drag_start: Option<Point>,
+    // Shape being drawn by the current drag, not yet sent to others
+    pending_shape: Option<Shape>,
+    // Shape being dragged around by the move tool, as it was before the drag
+    moving: Option<ShapeEntry>,
+    shapes: Vec<ShapeEntry>,
+    // Our own operations, to undo/redo
+    history: History,
     other_pointers: HashMap<String, LiveCursor>,
+    // Everyone else connected to the board
+    participants: Vec<Participant>,
     client: WSClient,
-    color: Color,
-    id: String,
+    connection: ConnectionState,
+    // Our operations the server hasn't acknowledged yet, re-applied on top of what we load in
+    // case it hasn't persisted them
+    outbox: Outbox,
+    // Sequence number of the last board event our shapes include, once loaded
+    last_seq: Option<i64>,
+    // Whether shapes or missed events are being fetched. Events received meanwhile wait in
+    // pending_events, to be applied relative to what the request returns
+    fetching: bool,
+    pending_events: Vec<BoardEvent>,
+    identity: Identity,
     last_pointer_update: f64,
     performance: web_sys::Performance,
     loading: bool,
+    // Name and dates, fetched once when the board is opened
+    details: Option<BoardDetails>,
+    // Why the board couldn't be loaded, if it couldn't
+    load_error: Option<String>,
+    // Why our last operation was refused or our access changed, if it did
+    rejected: Option<String>,
+    // Invite links of the board, while the owner has them shown
+    invites: Option<Vec<Invite>>,
+    // Undo/redo shortcuts, listened to on the whole window until the board is left
+    undo_keys: Option<Closure<dyn FnMut(web_sys::KeyboardEvent)>>,
 }
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct BoardProps {
     pub id: i32,
+    // Token of the invite link the board was opened with
+    #[prop_or_default]
+    pub invite: Option<String>,
+    // Read-only, without toolbar nor pointer, to be shown in other sites
+    #[prop_or_default]
+    pub embed: bool,
 }
 
 // Mouse position spline:
@@ -58,69 +172,98 @@ impl Component for Board {
         let board_id = ctx.props().id;
         log::info!("Initializing board={:?}", board_id);
         let window = web_sys::window().unwrap();
-        let crypto = window.crypto().unwrap();
         let scope = ctx.link().clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            match fetch_shapes(board_id).await {
-                Ok(shapes) => {
-                    scope.send_message(Msg::OnInitialShapesLoaded(shapes));
-                }
-                Err(e) => {
-                    log::error!("Error loading shapes: {:?}", e);
-                    scope.send_message(Msg::OnLoadError);
-                }
-            }
-        });
-        let scope = ctx.link().clone();
-        let client = new_ws_client(board_id, move |message: WsMessage| match message {
+        let state_scope = ctx.link().clone();
+        let on_state = move |state| state_scope.send_message(Msg::ConnectionStateChanged(state));
+        let on_message = move |message: WsMessage| match message {
             WsMessage::Text(value) => {
                 // log::info!("String message {}", value);
-                let m: SocketMessage = serde_json::from_str(&value).unwrap();
+                // E.g. from a newer server, the rest of the board still works
+                let m: ServerMessage = match serde_json::from_str(&value) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        log::warn!("Ignoring unknown server message {:?}: {}", value, e);
+                        return;
+                    }
+                };
                 match m {
-                    SocketMessage::Circle(circle) => {
-                        // log::info!("circle message {:?}", circle);
-                        scope.send_message(Msg::NewCircle(circle));
+                    ServerMessage::Event(event) => {
+                        scope.send_message(Msg::EventReceived(event));
                     }
-                    SocketMessage::Pointer(pointer_position) => {
+                    ServerMessage::Pointer(pointer_position) => {
                         // log::info!("pointer update {:?}", pointer_position);
                         scope.send_message(Msg::OtherPointerMoved(pointer_position));
                     }
+                    ServerMessage::BoardDeleted => {
+                        scope.send_message(Msg::BoardDeleted);
+                    }
+                    ServerMessage::Rejected(reason) => {
+                        scope.send_message(Msg::Rejected(reason));
+                    }
+                    ServerMessage::AccessChanged(reason) => {
+                        scope.send_message(Msg::AccessChanged(reason));
+                    }
+                    ServerMessage::Ack => {
+                        scope.send_message(Msg::Acknowledged);
+                    }
+                    ServerMessage::PresenceSnapshot(participants) => {
+                        scope.send_message(Msg::PresenceSnapshot(participants));
+                    }
+                    ServerMessage::Joined(participant) => {
+                        scope.send_message(Msg::Joined(participant));
+                    }
+                    ServerMessage::Left(id) => {
+                        scope.send_message(Msg::Left(id));
+                    }
                 }
             }
             WsMessage::Bytes(_value) => {
                 log::info!("Bytes message");
             }
-        });
-        ctx.link().send_message(Msg::Draw);
-        let mut tmp = [0u8, 0u8, 0u8];
-        crypto.get_random_values_with_u8_array(&mut tmp).unwrap();
-        let color = Color {
-            r: tmp[0],
-            g: tmp[1],
-            b: tmp[2],
         };
+        let invite = ctx.props().invite.clone();
+        let embed = ctx.props().embed;
+        let client = new_ws_client(board_id, invite.as_deref(), embed, on_message, on_state);
+        ctx.link().send_message(Msg::Draw);
+        Self::load_details(ctx);
         let performance = window
             .performance()
             .expect("window.performance should be available");
         Self {
             canvas_ref: NodeRef::default(),
-            button_pressed: false,
-            circles: Vec::new(),
+            tool: Tool::Dot,
+            viewport: Viewport::default(),
+            panning: None,
+            drag_start: None,
+            pending_shape: None,
+            moving: None,
+            shapes: Vec::new(),
+            history: History::new(),
             other_pointers: HashMap::new(),
+            participants: Vec::new(),
             client,
-            color,
-            id: color.hex_color(),
+            connection: ConnectionState::Connecting,
+            outbox: Outbox::load(board_id),
+            last_seq: None,
+            fetching: false,
+            pending_events: Vec::new(),
+            identity: Identity::load(),
             last_pointer_update: performance.now(),
             performance,
-            // OnInitialShapesLoaded will clear the loading flag
+            // OnShapesLoaded will clear the loading flag
             loading: true,
+            details: None,
+            load_error: None,
+            rejected: None,
+            invites: None,
+            undo_keys: None,
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::NewCircle(circle) => {
-                self.circles.push(circle);
+            Msg::EventReceived(event) => {
+                self.receive_event(ctx, event);
                 ctx.link().send_message(Msg::Draw);
                 false
             }
@@ -146,65 +289,150 @@ impl Component for Board {
                 for (_, live_cursor) in self.other_pointers.iter_mut() {
                     live_cursor.tick();
                 }
-                self.draw_circles(&canvas);
+                self.draw_shapes(&canvas);
                 self.draw_pointers(&canvas);
                 true
             }
-            Msg::ButtonPressed => {
-                self.button_pressed = true;
+            Msg::SelectTool(tool) => {
+                self.tool = tool;
+                true
+            }
+            Msg::Undo => {
+                let shapes = &self.shapes;
+                let op = self.history.undo(|id| shapes.iter().any(|e| e.id == id));
+                if let Some(op) = op {
+                    self.perform(ctx, &op);
+                }
+                true
+            }
+            Msg::Redo => {
+                let shapes = &self.shapes;
+                let op = self.history.redo(|id| shapes.iter().any(|e| e.id == id));
+                if let Some(op) = op {
+                    self.perform(ctx, &op);
+                }
+                true
+            }
+            Msg::PanStarted(x, y) => {
+                self.panning = Some(screen_point(x, y));
+                false
+            }
+            Msg::Zoom(x, y, factor) => {
+                self.viewport.zoom_at(&screen_point(x, y), factor);
+                ctx.link().send_message(Msg::Draw);
+                false
+            }
+            Msg::ZoomToFit => {
+                let canvas = self.canvas_ref.cast::<HtmlCanvasElement>().unwrap();
+                if let Some(bounds) = Bounds::of_shapes(self.shapes.iter().map(|e| &e.shape)) {
+                    self.viewport = Viewport::fit(
+                        &bounds,
+                        canvas.width() as f64,
+                        canvas.height() as f64,
+                        FIT_MARGIN,
+                    );
+                }
+                ctx.link().send_message(Msg::Draw);
+                false
+            }
+            Msg::ButtonPressed(x, y) => {
+                if self.tool == Tool::Pan || self.read_only() {
+                    ctx.link().send_message(Msg::PanStarted(x, y));
+                    return false;
+                }
+                let point = self.viewport.to_world(&screen_point(x, y));
+                self.drag_start = Some(point);
+                match self.tool {
+                    Tool::Move => {
+                        self.moving = self
+                            .shape_at(&point)
+                            .and_then(|id| self.shapes.iter().find(|e| e.id == id).cloned())
+                    }
+                    Tool::Eraser => self.erase_at(ctx, &point),
+                    _ => self.pending_shape = self.shape_from_drag(point, point),
+                }
                 false
             }
             Msg::ButtonReleased(x, y) => {
-                if self.button_pressed {
-                    let circle = Circle {
-                        x: x as f64,
-                        y: y as f64,
-                        radius: 5.0,
-                        color: self.color,
+                if self.panning.take().is_some() {
+                    return false;
+                }
+                if let Some(start) = self.drag_start.take() {
+                    let end = self.viewport.to_world(&screen_point(x, y));
+                    let shape = match self.tool {
+                        // Strokes accumulate points while dragging
+                        Tool::Pen => self.pending_shape.take(),
+                        _ => self.shape_from_drag(start, end),
                     };
-                    let mut client = self.client.clone();
-                    let circle2 = circle.clone();
-                    ctx.link().send_future(async move {
-                        let m = SocketMessage::Circle(circle2);
-                        let jsonval = serde_json::to_string(&m).unwrap();
-                        client.sender.send(WsMessage::Text(jsonval)).await.unwrap();
-                        // TODO: This is not really needed
-                        Msg::Draw
-                    });
-                    self.circles.push(circle);
+                    self.pending_shape = None;
+                    if let Some(shape) = shape {
+                        let op = ShapeOperation::Create(ShapeEntry {
+                            id: new_shape_id(),
+                            shape,
+                        });
+                        self.perform(ctx, &op);
+                        self.history.record(op);
+                    }
+                    if let Some(before) = self.moving.take() {
+                        let after = self.shapes.iter().find(|e| e.id == before.id).cloned();
+                        // The shape might have been deleted by someone else while we dragged it
+                        if let Some(after) = after {
+                            if after.shape != before.shape {
+                                self.send(ctx, SocketMessage::ShapeUpdated(after.clone()));
+                                self.history
+                                    .record(ShapeOperation::Update { before, after });
+                            }
+                        }
+                    }
                     // Trigger a redraw
                     ctx.link().send_message(Msg::Draw);
                 }
-                self.button_pressed = false;
                 false
             }
             Msg::MouseMove(x, y) => {
+                let screen = screen_point(x, y);
+                if let Some(last) = self.panning {
+                    self.viewport.pan(screen.x - last.x, screen.y - last.y);
+                    self.panning = Some(screen);
+                }
+                let world = self.viewport.to_world(&screen);
+                if let Some(start) = self.drag_start {
+                    let point = world;
+                    match (self.tool, &mut self.pending_shape) {
+                        (Tool::Pen, Some(Shape::Stroke(stroke))) => stroke.points.push(point),
+                        (Tool::Move, _) => {
+                            let moving = self.moving.as_ref().map(|e| e.id);
+                            if let Some(entry) =
+                                self.shapes.iter_mut().find(|e| Some(e.id) == moving)
+                            {
+                                entry.shape.translate(point.x - start.x, point.y - start.y);
+                            }
+                            self.drag_start = Some(point);
+                        }
+                        (Tool::Eraser, _) => self.erase_at(ctx, &point),
+                        _ => self.pending_shape = self.shape_from_drag(start, point),
+                    }
+                }
                 let curr_time = self.performance.now();
                 if (curr_time - self.last_pointer_update) > 200.0 {
                     self.last_pointer_update = curr_time;
                     let pointer_pos = PointerPosition {
-                        id: self.id.clone(),
-                        x: x as f64,
-                        y: y as f64,
-                        color: self.color,
+                        id: self.identity.id.clone(),
+                        name: self.identity.name.clone(),
+                        x: world.x,
+                        y: world.y,
+                        color: self.identity.color,
                     };
-                    let mut client = self.client.clone();
-                    ctx.link().send_future(async move {
-                        let m = SocketMessage::Pointer(pointer_pos);
-                        let jsonval = serde_json::to_string(&m).unwrap();
-                        client.sender.send(WsMessage::Text(jsonval)).await.unwrap();
-                        Msg::Draw
-                    });
+                    self.send(ctx, SocketMessage::Pointer(pointer_pos));
                 }
                 false
             }
             Msg::OtherPointerMoved(pointer_position) => {
                 let key = pointer_position.id.clone();
-                if self.other_pointers.contains_key(&key) {
-                    self.other_pointers
-                        .get_mut(&key)
-                        .unwrap()
-                        .add_point(pointer_position.x, pointer_position.y);
+                if let Some(live_cursor) = self.other_pointers.get_mut(&key) {
+                    // Guests may have renamed themselves in the meantime
+                    live_cursor.name = pointer_position.name;
+                    live_cursor.add_point(pointer_position.x, pointer_position.y);
                 } else {
                     self.other_pointers.insert(
                         pointer_position.id.clone(),
@@ -214,16 +442,207 @@ impl Component for Board {
                 ctx.link().send_message(Msg::Draw);
                 false
             }
-            Msg::OnLoadError => {
-                // TODO
+            Msg::PresenceSnapshot(participants) => {
+                self.other_pointers
+                    .retain(|id, _| participants.iter().any(|p| &p.id == id));
+                self.participants = participants;
+                true
+            }
+            Msg::Joined(participant) => {
+                log::info!("{} joined", participant.name);
+                self.participants.retain(|p| p.id != participant.id);
+                self.participants.push(participant);
+                true
+            }
+            Msg::Left(id) => {
+                self.participants.retain(|p| p.id != id);
+                // Their cursor would otherwise stay where they left it
+                self.other_pointers.remove(&id);
+                ctx.link().send_message(Msg::Draw);
+                true
+            }
+            Msg::BoardDeleted => {
+                window()
+                    .alert_with_message("This board was deleted")
+                    .unwrap();
+                // Leaving the page drops the socket client, which stops reconnecting
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::BoardsList);
+                }
                 false
             }
-            Msg::OnInitialShapesLoaded(shapes) => {
-                for shape in shapes {
-                    match shape {
-                        Shape::Circle(circle) => self.circles.push(circle),
+            Msg::Acknowledged => {
+                self.outbox.acknowledge();
+                // Updates the count of unsynced changes
+                true
+            }
+            Msg::AccessChanged(reason) => {
+                log::warn!("Access changed: {}", reason);
+                self.rejected = Some(reason);
+                // For our new role
+                Self::load_details(ctx);
+                true
+            }
+            Msg::Rejected(reason) => {
+                log::warn!("Operation rejected: {}", reason);
+                self.outbox.acknowledge();
+                self.rejected = Some(reason);
+                // Drop what we drew locally
+                self.load_shapes(ctx);
+                true
+            }
+            Msg::ToggleInvites => {
+                match self.invites.take() {
+                    Some(_) => return true,
+                    None => self.load_invites(ctx),
+                }
+                false
+            }
+            Msg::InvitesLoaded(invites) => {
+                self.invites = Some(invites);
+                true
+            }
+            Msg::CreateInvite(role) => {
+                let hours = window()
+                    .prompt_with_message("Expire after how many hours? (empty: never)")
+                    .unwrap();
+                let expires_in_hours = match hours.as_deref().map(str::trim) {
+                    None => return false,
+                    Some("") => None,
+                    Some(hours) => match hours.parse::<i64>() {
+                        Ok(hours) => Some(hours),
+                        Err(_) => {
+                            window().alert_with_message("Not a number").unwrap();
+                            return false;
+                        }
+                    },
+                };
+                let (board_id, link) = (ctx.props().id, ctx.link().clone());
+                ctx.link().send_future(async move {
+                    match create_invite(board_id, role, expires_in_hours).await {
+                        Ok(invite) => {
+                            link.send_message(Msg::CopyInvite(invite.token));
+                            Msg::ToggleInvites
+                        }
+                        Err(err) => Msg::InviteError(err),
+                    }
+                });
+                // Reloaded by ToggleInvites
+                self.invites = None;
+                true
+            }
+            Msg::CopyInvite(token) => {
+                let link = format!(
+                    "{}/invites/{}",
+                    window().location().origin().unwrap(),
+                    token
+                );
+                window()
+                    .prompt_with_message_and_default("Share this link", &link)
+                    .unwrap();
+                false
+            }
+            Msg::CopyEmbed(token) => {
+                let snippet = format!(
+                    r#"<iframe src="{}/embed/{}" width="800" height="600"></iframe>"#,
+                    window().location().origin().unwrap(),
+                    token
+                );
+                window()
+                    .prompt_with_message_and_default("Paste this in a web page", &snippet)
+                    .unwrap();
+                false
+            }
+            Msg::RevokeInvite(invite_id) => {
+                let board_id = ctx.props().id;
+                ctx.link().send_future(async move {
+                    match delete_invite(board_id, invite_id).await {
+                        Ok(()) => Msg::ToggleInvites,
+                        Err(err) => Msg::InviteError(err),
+                    }
+                });
+                self.invites = None;
+                true
+            }
+            Msg::InviteError(err) => {
+                window().alert_with_message(&err.message).unwrap();
+                false
+            }
+            Msg::OnLoadError(err) => {
+                log::error!("Error loading board: {:?}", err);
+                self.loading = false;
+                self.fetching = false;
+                self.load_error = Some(match err.code.as_str() {
+                    "not_found" => "This board doesn't exist".to_string(),
+                    _ => err.message,
+                });
+                true
+            }
+            Msg::ConnectionStateChanged(state) => {
+                log::info!("Connection state: {:?}", state);
+                self.connection = state;
+                // (Re)load shapes once connected, so we don't miss any change made while we
+                // were offline
+                if state == ConnectionState::Connected {
+                    // Embedded boards watch without being seen
+                    if !ctx.props().embed {
+                        let hello = SocketMessage::Hello(self.identity.participant());
+                        self.send_now(ctx, hello);
+                    }
+                    // They may have been lost with the previous connection. The server ignores
+                    // those it already handled
+                    if !self.outbox.is_empty() {
+                        log::info!("Replaying {} unacknowledged operations", self.outbox.len());
+                    }
+                    for op in self.outbox.operations().to_vec() {
+                        self.send_now(ctx, SocketMessage::Operation(op));
                     }
+                    match self.last_seq {
+                        Some(seq) => self.catch_up(ctx, seq),
+                        None => self.load_shapes(ctx),
+                    }
+                } else {
+                    // We'll get a fresh snapshot once reconnected
+                    self.participants.clear();
+                    self.other_pointers.clear();
+                    if self.loading && matches!(state, ConnectionState::Disconnected { .. }) {
+                        // Show what we can while the socket is down, or why it can't connect
+                        self.load_shapes(ctx);
+                    }
+                }
+                true
+            }
+            Msg::OnDetailsLoaded(details) => {
+                self.details = Some(details);
+                if self.read_only() {
+                    self.tool = Tool::Pan;
+                }
+                true
+            }
+            Msg::OnShapesLoaded(BoardShapes { shapes, last_seq }) => {
+                self.shapes = shapes;
+                self.last_seq = Some(last_seq);
+                self.load_error = None;
+                self.fetching = false;
+                for event in std::mem::take(&mut self.pending_events) {
+                    self.receive_event(ctx, event);
+                }
+                self.apply_outbox();
+                // Embedded boards can't be panned nor zoomed, so they show everything
+                if ctx.props().embed && self.loading {
+                    ctx.link().send_message(Msg::ZoomToFit);
+                }
+                ctx.link().send_message(Msg::Draw);
+                self.loading = false;
+                false
+            }
+            Msg::OnEventsLoaded(events) => {
+                self.fetching = false;
+                let pending = std::mem::take(&mut self.pending_events);
+                for event in events.into_iter().chain(pending) {
+                    self.receive_event(ctx, event);
                 }
+                self.apply_outbox();
                 ctx.link().send_message(Msg::Draw);
                 self.loading = false;
                 false
@@ -233,35 +652,10 @@ impl Component for Board {
 
     fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
         if first_render {
-            // Good example with more complex mouse state tracking
-            // https://rustwasm.github.io/wasm-bindgen/examples/paint.html
-            self.add_canvas_event_listener(
-                ctx,
-                "mousedown",
-                move |_event: web_sys::MouseEvent, scope| {
-                    log::info!("MouseDown !");
-                    scope.send_future(async { Msg::ButtonPressed })
-                },
-            );
-            self.add_canvas_event_listener(
-                ctx,
-                "mouseup",
-                move |event: web_sys::MouseEvent, scope| {
-                    log::info!("MouseUp !");
-                    let x = event.offset_x();
-                    let y = event.offset_y();
-                    scope.send_future(async move { Msg::ButtonReleased(x, y) })
-                },
-            );
-            self.add_canvas_event_listener(
-                ctx,
-                "mousemove",
-                move |event: web_sys::MouseEvent, scope| {
-                    let x = event.offset_x();
-                    let y = event.offset_y();
-                    scope.send_future(async move { Msg::MouseMove(x, y) })
-                },
-            );
+            // Embedded boards are only looked at
+            if !ctx.props().embed {
+                self.listen_to_input(ctx);
+            }
             // Setup regular redraw to animate other pointers
             {
                 let scope = ctx.link().clone();
@@ -276,18 +670,40 @@ impl Component for Board {
         }
     }
 
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        // Other pages have their own uses for Ctrl+Z, like their text inputs
+        if let Some(closure) = self.undo_keys.take() {
+            window()
+                .remove_event_listener_with_callback("keydown", closure.as_ref().unchecked_ref())
+                .unwrap();
+        }
+    }
+
     fn view(&self, ctx: &Context<Self>) -> Html {
+        if ctx.props().embed {
+            return html! {
+                <div aria-busy={ if self.loading { "true" } else { "false" }}>
+                    <canvas ref={self.canvas_ref.clone()} height="500" width="500" />
+                </div>
+            };
+        }
         let style = format!(
             "width: 100%; height: 10px; background-color: {}",
-            self.color.hex_color()
+            self.identity.color.hex_color()
         );
         html! {
             <div aria-busy={ if self.loading { "true" } else { "false" }}>
                 <div style="position: absolute; bottom: 0; left: 0; margin: 5px;">
-                    <p>{ self.circles.len() } { " circles" } </p>
+                    <p>{ self.shapes.len() } { " shapes" } </p>
+                    <p>{ format!("zoom: {:.0}%", self.viewport.scale * 100.0) }</p>
                     <p>{"id: "}{ ctx.props().id.to_owned() }</p>
+                    { self.view_dates() }
                 </div>
                 <div { style }></div>
+                { self.view_toolbar(ctx) }
+                { self.view_connection_state() }
+                { self.view_invites(ctx) }
+                { self.view_participants() }
                 <canvas
                     ref={self.canvas_ref.clone()}
                     height="500"
@@ -299,6 +715,71 @@ impl Component for Board {
 }
 
 impl Board {
+    // Mouse and keyboard handlers, for drawing and moving around
+    fn listen_to_input(&mut self, ctx: &Context<Self>) {
+        // Good example with more complex mouse state tracking
+        // https://rustwasm.github.io/wasm-bindgen/examples/paint.html
+        self.add_canvas_event_listener(
+            ctx,
+            "mousedown",
+            move |event: web_sys::MouseEvent, scope| {
+                log::info!("MouseDown !");
+                let x = event.offset_x();
+                let y = event.offset_y();
+                // Middle button pans whatever the current tool
+                if event.button() == 1 {
+                    event.prevent_default();
+                    scope.send_future(async move { Msg::PanStarted(x, y) })
+                } else {
+                    scope.send_future(async move { Msg::ButtonPressed(x, y) })
+                }
+            },
+        );
+        self.add_canvas_event_listener(ctx, "mouseup", move |event: web_sys::MouseEvent, scope| {
+            log::info!("MouseUp !");
+            let x = event.offset_x();
+            let y = event.offset_y();
+            scope.send_future(async move { Msg::ButtonReleased(x, y) })
+        });
+        self.add_canvas_event_listener(
+            ctx,
+            "mousemove",
+            move |event: web_sys::MouseEvent, scope| {
+                let x = event.offset_x();
+                let y = event.offset_y();
+                scope.send_future(async move { Msg::MouseMove(x, y) })
+            },
+        );
+        self.add_canvas_event_listener(ctx, "wheel", move |event: web_sys::WheelEvent, scope| {
+            event.prevent_default();
+            let x = event.offset_x();
+            let y = event.offset_y();
+            let factor = (-event.delta_y() * WHEEL_ZOOM_SPEED).exp();
+            scope.send_future(async move { Msg::Zoom(x, y, factor) })
+        });
+        // Undo: Ctrl+Z, redo: Ctrl+Shift+Z (Cmd instead of Ctrl on macOS)
+        {
+            let scope = ctx.link().clone();
+            let closure = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+                if !(event.ctrl_key() || event.meta_key()) || !event.key().eq_ignore_ascii_case("z")
+                {
+                    return;
+                }
+                event.prevent_default();
+                if event.shift_key() {
+                    scope.send_message(Msg::Redo);
+                } else {
+                    scope.send_message(Msg::Undo);
+                }
+            }) as Box<dyn FnMut(_)>);
+            window()
+                .add_event_listener_with_callback("keydown", closure.as_ref().unchecked_ref())
+                .unwrap();
+            // Removed in destroy
+            self.undo_keys = Some(closure);
+        }
+    }
+
     fn get_context(&self, canvas: &HtmlCanvasElement) -> web_sys::CanvasRenderingContext2d {
         canvas
             .get_context("2d")
@@ -307,22 +788,410 @@ impl Board {
             .dyn_into::<web_sys::CanvasRenderingContext2d>()
             .unwrap()
     }
-    fn draw_circles(&self, canvas: &HtmlCanvasElement) {
+    fn draw_shapes(&self, canvas: &HtmlCanvasElement) {
         let context = self.get_context(canvas);
+        let Viewport { origin, scale } = self.viewport;
+        context
+            .set_transform(scale, 0., 0., scale, -origin.x * scale, -origin.y * scale)
+            .unwrap();
 
-        for circle in &self.circles {
-            context.set_fill_style(&JsValue::from_str(&circle.color.hex_color()));
-            context.begin_path();
-            context
-                .arc(
-                    circle.x,
-                    circle.y,
-                    circle.radius,
-                    0.0,
-                    f64::consts::PI * 2.0,
-                )
-                .unwrap();
-            context.fill();
+        let shapes = self.shapes.iter().map(|e| &e.shape);
+        for shape in shapes.chain(self.pending_shape.iter()) {
+            draw_shape(&context, shape);
+        }
+    }
+
+    // Shape operations are queued in the outbox until acknowledged, and sent right away if we
+    // are connected. Pointer positions are dropped while disconnected. Embedded boards don't
+    // show their pointer to others
+    fn send(&mut self, ctx: &Context<Self>, m: SocketMessage) {
+        let is_pointer = matches!(m, SocketMessage::Pointer(_));
+        if ctx.props().embed && is_pointer {
+            return;
+        }
+        let m = match is_pointer {
+            true => m,
+            false => SocketMessage::Operation(self.outbox.push(m)),
+        };
+        if self.connection == ConnectionState::Connected {
+            self.send_now(ctx, m);
+        }
+    }
+
+    fn send_now(&self, ctx: &Context<Self>, m: SocketMessage) {
+        let mut client = self.client.clone();
+        ctx.link().send_future(async move {
+            let jsonval = serde_json::to_string(&m).unwrap();
+            if let Err(e) = client.sender.send(WsMessage::Text(jsonval)).await {
+                // Shape operations stay in the outbox, and are sent again once reconnected
+                log::warn!("Error sending message: {}", e);
+            }
+            Msg::Draw
+        });
+    }
+
+    // Our pending operations, which what we loaded may not include yet
+    fn apply_outbox(&mut self) {
+        for op in self.outbox.operations().to_vec() {
+            self.apply_message(*op.message);
+        }
+    }
+
+    // Creating a shape that already exists (e.g. our own message echoed back) replaces it
+    fn apply_created(&mut self, entry: ShapeEntry) {
+        match self.shapes.iter_mut().find(|e| e.id == entry.id) {
+            Some(existing) => *existing = entry,
+            None => self.shapes.push(entry),
+        }
+    }
+
+    fn apply_updated(&mut self, entry: ShapeEntry) {
+        match self.shapes.iter_mut().find(|e| e.id == entry.id) {
+            Some(existing) => *existing = entry,
+            None => log::warn!("Update for unknown shape {}", entry.id),
+        }
+    }
+
+    fn apply_deleted(&mut self, shape_id: ShapeId) {
+        self.shapes.retain(|e| e.id != shape_id);
+    }
+
+    // Applies `event` if it is the next one, and fetches those we missed if it isn't
+    fn receive_event(&mut self, ctx: &Context<Self>, event: BoardEvent) {
+        if self.fetching {
+            self.pending_events.push(event);
+            return;
+        }
+        match self.last_seq {
+            Some(seq) if event.seq <= seq => {
+                // Already included, e.g. in the shapes we loaded after it was made
+            }
+            Some(seq) if event.seq > seq + 1 => {
+                log::warn!("Missed events {}..{}, catching up", seq + 1, event.seq);
+                self.catch_up(ctx, seq);
+                self.pending_events.push(event);
+            }
+            Some(_) => {
+                self.last_seq = Some(event.seq);
+                self.apply_message(event.message);
+            }
+            // Loading the shapes failed, we'll load them again once reconnected
+            None => {}
+        }
+    }
+
+    // Fetches the events after `since`, to apply those we missed
+    fn catch_up(&mut self, ctx: &Context<Self>, since: i64) {
+        if self.fetching {
+            return;
+        }
+        self.fetching = true;
+        let (board_id, invite) = (ctx.props().id, ctx.props().invite.clone());
+        ctx.link().send_future(async move {
+            match fetch_events(board_id, since, invite.as_deref()).await {
+                Ok(events) => Msg::OnEventsLoaded(events),
+                Err(e) => Msg::OnLoadError(e),
+            }
+        });
+    }
+
+    fn load_shapes(&mut self, ctx: &Context<Self>) {
+        self.fetching = true;
+        let (board_id, invite) = (ctx.props().id, ctx.props().invite.clone());
+        ctx.link().send_future(async move {
+            match fetch_shapes(board_id, invite.as_deref()).await {
+                Ok(shapes) => Msg::OnShapesLoaded(shapes),
+                Err(e) => Msg::OnLoadError(e),
+            }
+        });
+    }
+
+    fn load_details(ctx: &Context<Self>) {
+        let (board_id, invite) = (ctx.props().id, ctx.props().invite.clone());
+        ctx.link().send_future(async move {
+            match fetch_board(board_id, invite.as_deref()).await {
+                Ok(details) => Msg::OnDetailsLoaded(details),
+                Err(e) => Msg::OnLoadError(e),
+            }
+        });
+    }
+
+    fn load_invites(&self, ctx: &Context<Self>) {
+        let board_id = ctx.props().id;
+        ctx.link().send_future(async move {
+            match fetch_invites(board_id).await {
+                Ok(invites) => Msg::InvitesLoaded(invites),
+                Err(e) => Msg::InviteError(e),
+            }
+        });
+    }
+
+    fn apply_message(&mut self, m: SocketMessage) {
+        match m {
+            SocketMessage::ShapeCreated(entry) => self.apply_created(entry),
+            SocketMessage::ShapeUpdated(entry) => self.apply_updated(entry),
+            SocketMessage::ShapeDeleted(shape_id) => self.apply_deleted(shape_id),
+            SocketMessage::Operation(op) => self.apply_message(*op.message),
+            SocketMessage::Pointer(_) | SocketMessage::Hello(_) => {}
+        }
+    }
+
+    // Topmost shape under `point`
+    fn shape_at(&self, point: &Point) -> Option<ShapeId> {
+        self.shapes
+            .iter()
+            .rev()
+            .find(|e| e.shape.contains(point, HIT_TOLERANCE / self.viewport.scale))
+            .map(|e| e.id)
+    }
+
+    fn erase_at(&mut self, ctx: &Context<Self>, point: &Point) {
+        let entry = self
+            .shape_at(point)
+            .and_then(|id| self.shapes.iter().find(|e| e.id == id).cloned());
+        if let Some(entry) = entry {
+            let op = ShapeOperation::Delete(entry);
+            self.perform(ctx, &op);
+            self.history.record(op);
+        }
+    }
+
+    // Applies `op` locally and sends it to the other clients and the server
+    fn perform(&mut self, ctx: &Context<Self>, op: &ShapeOperation) {
+        match op {
+            ShapeOperation::Create(entry) => self.apply_created(entry.clone()),
+            ShapeOperation::Update { after, .. } => self.apply_updated(after.clone()),
+            ShapeOperation::Delete(entry) => self.apply_deleted(entry.id),
+        }
+        self.send(ctx, op.to_message());
+        ctx.link().send_message(Msg::Draw);
+    }
+
+    // Builds the shape for the current tool when dragging from `start` to `end`
+    fn shape_from_drag(&self, start: Point, end: Point) -> Option<Shape> {
+        let color = self.identity.color;
+        let shape = match self.tool {
+            Tool::Dot => Shape::Circle(Circle {
+                x: end.x,
+                y: end.y,
+                radius: 5.0,
+                color,
+            }),
+            Tool::Rectangle => Shape::Rectangle(Rectangle {
+                x: start.x.min(end.x),
+                y: start.y.min(end.y),
+                width: (end.x - start.x).abs(),
+                height: (end.y - start.y).abs(),
+                stroke_width: STROKE_WIDTH,
+                color,
+            }),
+            Tool::Ellipse => Shape::Ellipse(Ellipse {
+                x: (start.x + end.x) / 2.0,
+                y: (start.y + end.y) / 2.0,
+                radius_x: (end.x - start.x).abs() / 2.0,
+                radius_y: (end.y - start.y).abs() / 2.0,
+                stroke_width: STROKE_WIDTH,
+                color,
+            }),
+            Tool::Line => Shape::Line(Line {
+                start,
+                end,
+                stroke_width: STROKE_WIDTH,
+                color,
+            }),
+            Tool::Arrow => Shape::Arrow(Arrow {
+                start,
+                end,
+                stroke_width: STROKE_WIDTH,
+                color,
+            }),
+            Tool::Pen => Shape::Stroke(Stroke {
+                points: vec![start, end],
+                stroke_width: STROKE_WIDTH,
+                color,
+            }),
+            Tool::Move | Tool::Eraser | Tool::Pan => return None,
+        };
+        // A simple click only makes sense for dots and freehand strokes
+        let is_click = start == end;
+        match self.tool {
+            Tool::Dot | Tool::Pen => Some(shape),
+            _ if is_click => None,
+            _ => Some(shape),
+        }
+    }
+
+    fn view_dates(&self) -> Html {
+        match &self.details {
+            Some(details) => html! {
+                <>
+                    <p>{ format!("created: {} UTC", details.board.created_at.format("%Y-%m-%d %H:%M")) }</p>
+                    <p>{ format!("modified: {} UTC", details.last_modified.format("%Y-%m-%d %H:%M")) }</p>
+                </>
+            },
+            None => html! {},
+        }
+    }
+
+    // Viewers can only look around
+    fn read_only(&self) -> bool {
+        self.details
+            .as_ref()
+            .is_some_and(|d| d.role == Role::Viewer)
+    }
+
+    fn view_toolbar(&self, ctx: &Context<Self>) -> Html {
+        let name = self.details.as_ref().map_or("", |d| d.board.name.as_str());
+        let mut export_url = format!("/api/boards/{}/export.svg", ctx.props().id);
+        if let Some(token) = &ctx.props().invite {
+            export_url = format!("{}?invite={}", export_url, token);
+        }
+        let export = html! {
+            <a role="button" class="secondary" download="" href={export_url}>
+                { "Export SVG" }
+            </a>
+        };
+        let zoom_to_fit = html! {
+            <button class="secondary" onclick={ctx.link().callback(|_| Msg::ZoomToFit)}>
+                { "Zoom to fit" }
+            </button>
+        };
+        if self.read_only() {
+            return html! {
+                <div style="position: absolute; top: 15px; left: 5px; display: flex; gap: 5px;">
+                    <strong style="align-self: center; margin-right: 10px;">{ name }</strong>
+                    <span style="align-self: center; margin-right: 10px;">{ "(view only)" }</span>
+                    { zoom_to_fit }
+                    { export }
+                </div>
+            };
+        }
+        html! {
+            <div style="position: absolute; top: 15px; left: 5px; display: flex; gap: 5px;">
+                <strong style="align-self: center; margin-right: 10px;">{ name }</strong>
+                { for TOOLS.iter().map(|(tool, label)| {
+                    let tool = *tool;
+                    let onclick = ctx.link().callback(move |_| Msg::SelectTool(tool));
+                    let class = if tool == self.tool { "" } else { "outline" };
+                    html! {
+                        <button {class} {onclick}>{ label }</button>
+                    }
+                }) }
+                <button
+                    class="secondary"
+                    title="Ctrl+Z"
+                    disabled={!self.history.can_undo()}
+                    onclick={ctx.link().callback(|_| Msg::Undo)}>
+                    { "Undo" }
+                </button>
+                <button
+                    class="secondary"
+                    title="Ctrl+Shift+Z"
+                    disabled={!self.history.can_redo()}
+                    onclick={ctx.link().callback(|_| Msg::Redo)}>
+                    { "Redo" }
+                </button>
+                { zoom_to_fit }
+                { export }
+                if self.details.as_ref().is_some_and(|d| d.role == Role::Owner) {
+                    <button class="secondary" onclick={ctx.link().callback(|_| Msg::ToggleInvites)}>
+                        { "Invite links" }
+                    </button>
+                }
+            </div>
+        }
+    }
+
+    fn view_invites(&self, ctx: &Context<Self>) -> Html {
+        let invites = match &self.invites {
+            Some(invites) => invites,
+            None => return html! {},
+        };
+        let link = ctx.link();
+        html! {
+            <div class="overlay" style="top: 70px; right: 5px; border-radius: 6px; max-width: 400px;">
+                <strong>{ "Invite links" }</strong>
+                if invites.is_empty() {
+                    <p>{ "Nobody can join with a link yet" }</p>
+                }
+                { for invites.iter().map(|invite| {
+                    let (id, token) = (invite.id, invite.token.clone());
+                    // Embedded boards end up in public pages, so only view links are offered
+                    let embed_token = Some(token.clone()).filter(|_| invite.role == Role::Viewer);
+                    let expiry = match invite.expires_at {
+                        Some(expires_at) => format!("until {} UTC", expires_at.format("%Y-%m-%d %H:%M")),
+                        None => "never expires".to_string(),
+                    };
+                    html! {
+                        <p>
+                            { format!("{}, {} ", invite.role.as_str(), expiry) }
+                            <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                                e.prevent_default();
+                                Msg::CopyInvite(token.clone())
+                            })}>{ "Copy" }</a>
+                            { " " }
+                            if let Some(embed_token) = embed_token {
+                                <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                                    e.prevent_default();
+                                    Msg::CopyEmbed(embed_token.clone())
+                                })}>{ "Embed" }</a>
+                                { " " }
+                            }
+                            <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                                e.prevent_default();
+                                Msg::RevokeInvite(id)
+                            })}>{ "Revoke" }</a>
+                        </p>
+                    }
+                }) }
+                <button onclick={link.callback(|_| Msg::CreateInvite(Role::Viewer))}>
+                    { "New view link" }
+                </button>
+                <button onclick={link.callback(|_| Msg::CreateInvite(Role::Editor))}>
+                    { "New edit link" }
+                </button>
+            </div>
+        }
+    }
+
+    fn view_participants(&self) -> Html {
+        let me = self.identity.participant();
+        let dot = |p: &Participant| {
+            html! {
+                <span style={format!("color: {};", p.color.hex_color())}>{ "\u{25CF} " }</span>
+            }
+        };
+        html! {
+            <div class="overlay" style="top: auto; bottom: 5px; right: 5px; border-radius: 6px;">
+                <strong>{ format!("Online ({})", self.participants.len() + 1) }</strong>
+                <div>{ dot(&me) }{ format!("{} (you)", me.name) }</div>
+                { for self.participants.iter().map(|p| html! {
+                    <div>{ dot(p) }{ &p.name }</div>
+                }) }
+            </div>
+        }
+    }
+
+    fn view_connection_state(&self) -> Html {
+        let (color, mut label) = match self.connection {
+            ConnectionState::Connected => ("#2e7d32", "Connected".to_string()),
+            ConnectionState::Connecting => ("#f9a825", "Connecting...".to_string()),
+            ConnectionState::Disconnected { retry_in_ms } => (
+                "#c62828",
+                format!("Offline, retrying in {}s", (retry_in_ms + 999) / 1000),
+            ),
+        };
+        if !self.outbox.is_empty() {
+            label = format!("{} ({} unsynced changes)", label, self.outbox.len());
+        }
+        if let Some(error) = self.load_error.as_ref().or(self.rejected.as_ref()) {
+            label = format!("{} - {}", label, error);
+        }
+        html! {
+            <div class="overlay" style="top: 15px; border-radius: 6px;">
+                <span style={format!("color: {};", color)}>{ "\u{25CF} " }</span>
+                { label }
+            </div>
         }
     }
 
@@ -333,16 +1202,26 @@ impl Board {
             Path2d::new_with_path_string("M 8.2,20.9 V 4.9 L 19.8,16.5 H 13 l -0.4,0.1 z").unwrap();
         for live_cursor in self.other_pointers.values() {
             let color = live_cursor.color;
-            let pos = live_cursor.current_position;
+            let (x, y) = live_cursor.current_position;
+            // Cursors are positioned in world coordinates but keep the same size at any zoom level
+            let pos = self.viewport.to_screen(&Point { x, y });
             context.set_fill_style(&JsValue::from_str(&color.hex_color()));
-            context.set_transform(1., 0., 0., 1., pos.0, pos.1).unwrap();
+            context.set_transform(1., 0., 0., 1., pos.x, pos.y).unwrap();
             context.fill_with_path_2d(&path);
+            // Name below the arrow, outlined in white to stay readable over drawings
+            context.set_font("12px sans-serif");
+            context.set_line_width(3.0);
+            context.set_line_join("round");
+            context.set_stroke_style(&JsValue::from_str("white"));
+            context.stroke_text(&live_cursor.name, 14.0, 32.0).unwrap();
+            context.fill_text(&live_cursor.name, 14.0, 32.0).unwrap();
         }
     }
 
-    fn add_canvas_event_listener<F>(&self, ctx: &Context<Self>, event: &str, cb: F)
+    fn add_canvas_event_listener<E, F>(&self, ctx: &Context<Self>, event: &str, cb: F)
     where
-        F: 'static + Fn(web_sys::MouseEvent, &Scope<Board>),
+        E: 'static + FromWasmAbi,
+        F: 'static + Fn(E, &Scope<Board>),
     {
         let canvas = self.canvas_ref.cast::<HtmlCanvasElement>().unwrap();
         let scope = ctx.link().clone();
@@ -353,3 +1232,10 @@ impl Board {
         closure.forget();
     }
 }
+
+fn screen_point(x: i32, y: i32) -> Point {
+    Point {
+        x: x as f64,
+        y: y as f64,
+    }
+}
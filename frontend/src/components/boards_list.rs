@@ -1,24 +1,58 @@
-use crate::api::{create_board, fetch_boards, APIError};
+use crate::api::{
+    create_board, delete_board, delete_board_permanently, fetch_boards, fetch_me, import_board,
+    logout, rename_board, restore_board, set_member, APIError,
+};
 use crate::routes::Route;
-use shared::datatypes::Board;
-use web_sys::{HtmlFormElement, HtmlInputElement};
+use crate::utils::window;
+use shared::datatypes::{
+    validate_board_name, Board, BoardArchive, BoardPage, BoardSort, CreateBoardRequest, Role, User,
+};
+use std::future::Future;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{File, HtmlFormElement, HtmlInputElement, HtmlSelectElement};
 use yew::html::Scope;
 use yew::prelude::*;
 use yew_router::components::Link;
 
 pub struct BoardsList {
     loading: bool,
+    // Signed in user, once known
+    user: Option<User>,
     boards: Vec<Board>,
+    // Whether `boards` are the archived boards rather than the active ones
+    show_archived: bool,
+    // Search box content, only boards whose name contains it are listed
+    query: String,
+    sort: BoardSort,
+    // Where the next page starts, None once every board is listed
+    next_cursor: Option<String>,
+    // Last error, shown until the next successful refresh
+    error: Option<String>,
     create_form_ref: NodeRef,
     create_form_name_ref: NodeRef,
 }
 
 #[derive(Debug)]
 pub enum Msg {
-    BoardsRefreshed(Vec<Board>),
+    // The page, the search and sort it was made for, and whether it follows the boards already
+    // listed
+    BoardsLoaded(BoardPage, String, BoardSort, bool),
     APIError(APIError),
     CreateBoard(String),
+    ImportBoard(File),
+    RenameBoard(Board),
+    ShareBoard(Board),
+    DeleteBoard(i32),
+    RestoreBoard(i32),
+    DeleteBoardPermanently(Board),
+    ShowArchived(bool),
+    Search(String),
+    Sort(BoardSort),
+    LoadMore,
     RefreshBoards,
+    UserLoaded(User),
+    SignOut,
+    SignedOut,
 }
 
 impl Component for BoardsList {
@@ -27,9 +61,21 @@ impl Component for BoardsList {
 
     fn create(ctx: &Context<Self>) -> Self {
         ctx.link().send_message(Msg::RefreshBoards);
+        ctx.link().send_future(async {
+            match fetch_me().await {
+                Ok(user) => Msg::UserLoaded(user),
+                Err(err) => Msg::APIError(err),
+            }
+        });
         Self {
             loading: true,
+            user: None,
             boards: vec![],
+            show_archived: false,
+            query: String::new(),
+            sort: BoardSort::default(),
+            next_cursor: None,
+            error: None,
             create_form_ref: NodeRef::default(),
             create_form_name_ref: NodeRef::default(),
         }
@@ -38,33 +84,153 @@ impl Component for BoardsList {
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         log::info!("Msg::{:?}", msg);
         match msg {
-            Msg::BoardsRefreshed(resp) => {
-                self.boards = resp;
+            Msg::BoardsLoaded(page, query, sort, append) => {
+                // Answer to a search that has been typed over, or a sort changed, since
+                if query != self.query || sort != self.sort {
+                    return false;
+                }
+                if append {
+                    self.boards.extend(page.boards);
+                } else {
+                    self.boards = page.boards;
+                }
+                self.next_cursor = page.next_cursor;
                 self.loading = false;
+                self.error = None;
                 true
             }
             Msg::APIError(err) => {
                 log::error!("error: {:?}", err);
+                self.loading = false;
+                self.error = Some(err.message);
                 true
             }
             Msg::CreateBoard(name) => {
+                if let Err(message) = (CreateBoardRequest { name: name.clone() }).validate() {
+                    self.error = Some(message);
+                    return true;
+                }
                 let form = self.create_form_ref.cast::<HtmlFormElement>().unwrap();
                 form.reset();
                 ctx.link().send_future(async move {
-                    create_board(name).await.unwrap();
-                    Msg::RefreshBoards
+                    match create_board(name).await {
+                        Ok(_) => Msg::RefreshBoards,
+                        Err(err) => Msg::APIError(err),
+                    }
+                });
+                false
+            }
+            Msg::ImportBoard(file) => {
+                ctx.link().send_future(async move {
+                    let archive = match read_archive(&file).await {
+                        Ok(archive) => archive,
+                        Err(message) => {
+                            return Msg::APIError(APIError {
+                                code: "bad_request".to_string(),
+                                message,
+                            })
+                        }
+                    };
+                    match import_board(&archive).await {
+                        Ok(_) => Msg::RefreshBoards,
+                        Err(err) => Msg::APIError(err),
+                    }
                 });
                 false
             }
+            Msg::RenameBoard(board) => {
+                let name = window()
+                    .prompt_with_message_and_default("New name", &board.name)
+                    .unwrap();
+                match name {
+                    Some(name) if name != board.name => {
+                        if let Err(message) = validate_board_name(&name) {
+                            self.error = Some(message);
+                            return true;
+                        }
+                        self.then_refresh(ctx, rename_board(board.id, name));
+                    }
+                    _ => {}
+                }
+                false
+            }
+            Msg::ShareBoard(board) => {
+                let message = format!("Share \"{}\" with (username)", board.name);
+                let username = match window().prompt_with_message(&message).unwrap() {
+                    Some(username) if !username.trim().is_empty() => username.trim().to_string(),
+                    _ => return false,
+                };
+                // Cancel for read-only access
+                let role = match window()
+                    .confirm_with_message(&format!("Can {} draw on the board?", username))
+                    .unwrap()
+                {
+                    true => Role::Editor,
+                    false => Role::Viewer,
+                };
+                self.then_refresh(
+                    ctx,
+                    async move { set_member(board.id, &username, role).await },
+                );
+                false
+            }
+            Msg::DeleteBoard(board_id) => {
+                // Archived boards can be restored, no need to confirm
+                self.then_refresh(ctx, delete_board(board_id));
+                false
+            }
+            Msg::RestoreBoard(board_id) => {
+                self.then_refresh(ctx, restore_board(board_id));
+                false
+            }
+            Msg::DeleteBoardPermanently(board) => {
+                let message = format!("Delete \"{}\" and all its shapes for good?", board.name);
+                if window().confirm_with_message(&message).unwrap() {
+                    self.then_refresh(ctx, delete_board_permanently(board.id));
+                }
+                false
+            }
+            Msg::ShowArchived(show_archived) => {
+                self.show_archived = show_archived;
+                self.boards = vec![];
+                ctx.link().send_message(Msg::RefreshBoards);
+                true
+            }
+            Msg::Search(query) => {
+                self.query = query;
+                ctx.link().send_message(Msg::RefreshBoards);
+                false
+            }
+            Msg::Sort(sort) => {
+                self.sort = sort;
+                ctx.link().send_message(Msg::RefreshBoards);
+                false
+            }
+            Msg::LoadMore => {
+                let cursor = self.next_cursor.take();
+                self.load_boards(ctx, cursor);
+                true
+            }
             Msg::RefreshBoards => {
-                self.loading = true;
+                self.load_boards(ctx, None);
+                true
+            }
+            Msg::UserLoaded(user) => {
+                self.user = Some(user);
+                true
+            }
+            Msg::SignOut => {
                 ctx.link().send_future(async {
-                    match fetch_boards().await {
-                        Ok(resp) => Msg::BoardsRefreshed(resp),
+                    match logout().await {
+                        Ok(()) => Msg::SignedOut,
                         Err(err) => Msg::APIError(err),
                     }
                 });
-                true
+                false
+            }
+            Msg::SignedOut => {
+                window().location().set_href("/login").unwrap();
+                false
             }
         }
     }
@@ -72,33 +238,163 @@ impl Component for BoardsList {
     fn view(&self, ctx: &Context<Self>) -> Html {
         html! {
           <main class="container" aria-busy={ if self.loading { "true" } else { "false" } }>
-            { self.view_boards(&self.boards) }
-            { self.view_new_board_form(ctx.link()) }
+            { self.view_user(ctx.link()) }
+            { self.view_error() }
+            { self.view_boards(&self.boards, ctx.link()) }
+            { if self.show_archived { html! {} } else { self.view_new_board_form(ctx.link()) } }
           </main>
         }
     }
 }
 
 impl BoardsList {
-    fn view_board_card(&self, board: &Board) -> Html {
+    // The first page if `cursor` is None, the page it points to otherwise
+    fn load_boards(&mut self, ctx: &Context<Self>, cursor: Option<String>) {
+        self.loading = true;
+        let (archived, query, sort) = (self.show_archived, self.query.clone(), self.sort);
+        ctx.link().send_future(async move {
+            match fetch_boards(archived, &query, sort, cursor.as_deref()).await {
+                Ok(page) => Msg::BoardsLoaded(page, query, sort, cursor.is_some()),
+                Err(err) => Msg::APIError(err),
+            }
+        });
+    }
+    fn then_refresh<T: 'static>(
+        &self,
+        ctx: &Context<Self>,
+        request: impl Future<Output = Result<T, APIError>> + 'static,
+    ) {
+        ctx.link().send_future(async move {
+            match request.await {
+                Ok(_) => Msg::RefreshBoards,
+                Err(err) => Msg::APIError(err),
+            }
+        });
+    }
+    fn view_user(&self, link: &Scope<Self>) -> Html {
+        let user = match &self.user {
+            Some(user) => user,
+            None => return html! {},
+        };
+        let onclick = link.callback(|e: MouseEvent| {
+            e.prevent_default();
+            Msg::SignOut
+        });
+        html! {
+          <p style="text-align: right;">
+            { format!("Signed in as {} ", user.username) }
+            <a href="#" {onclick}>{"Sign out"}</a>
+          </p>
+        }
+    }
+    fn view_error(&self) -> Html {
+        match &self.error {
+            Some(message) => html! { <article role="alert">{message}</article> },
+            None => html! {},
+        }
+    }
+    fn view_board_card(&self, board: &Board, link: &Scope<Self>) -> Html {
+        let (id, b, s) = (board.id, board.clone(), board.clone());
+        if board.archived {
+            return html! {
+              <div>
+                <div class="card center"><span>{&board.name}</span></div>
+                <div class="card-actions">
+                  <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                      e.prevent_default();
+                      Msg::RestoreBoard(id)
+                  })}>{"Restore"}</a>
+                  <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                      e.prevent_default();
+                      Msg::DeleteBoardPermanently(b.clone())
+                  })}>{"Delete forever"}</a>
+                </div>
+              </div>
+            };
+        }
         html! {
-          <Link<Route> to={Route::BoardView { id: board.id }}>
-            <div class="card center">
-              <span>{&board.name}</span>
+          <div>
+            <Link<Route> to={Route::BoardView { id: board.id }}>
+              <div class="card center" style="flex-direction: column;">
+                <img
+                  src={format!("/api/boards/{}/thumbnail.png", board.id)}
+                  width="200"
+                  height="150"
+                  loading="lazy"
+                  alt="" />
+                <span>{&board.name}</span>
+              </div>
+            </Link<Route>>
+            <div class="card-actions">
+              <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                  e.prevent_default();
+                  Msg::RenameBoard(b.clone())
+              })}>{"Rename"}</a>
+              <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                  e.prevent_default();
+                  Msg::ShareBoard(s.clone())
+              })}>{"Share"}</a>
+              <a href={format!("/api/boards/{}/export", id)} download="">{"Export"}</a>
+              <a href="#" onclick={link.callback(move |e: MouseEvent| {
+                  e.prevent_default();
+                  Msg::DeleteBoard(id)
+              })}>{"Delete"}</a>
             </div>
-          </Link<Route>>
+          </div>
         }
     }
-    fn view_boards(&self, boards: &[Board]) -> Html {
+    fn view_boards(&self, boards: &[Board], link: &Scope<Self>) -> Html {
+        let show_archived = !self.show_archived;
+        let toggle = link.callback(move |e: MouseEvent| {
+            e.prevent_default();
+            Msg::ShowArchived(show_archived)
+        });
+        let load_more = link.callback(|_: MouseEvent| Msg::LoadMore);
         html! {
           <article>
-            <h3>{"Boards"}</h3>
+            <h3>{ if self.show_archived { "Archived boards" } else { "Boards" } }</h3>
+            { self.view_search(link) }
             <div class="cards-container">
-            { boards.iter().map(|b| self.view_board_card(b)).collect::<Html>() }
+            { boards.iter().map(|b| self.view_board_card(b, link)).collect::<Html>() }
             </div>
+            if self.next_cursor.is_some() {
+              <button class="secondary" onclick={load_more}>{"Load more"}</button>
+            }
+            <p>
+              <a href="#" onclick={toggle}>
+                { if self.show_archived { "Back to boards" } else { "Archived boards" } }
+              </a>
+            </p>
           </article>
         }
     }
+    fn view_search(&self, link: &Scope<Self>) -> Html {
+        let oninput = link.callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::Search(input.value())
+        });
+        let onchange = link.callback(|e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            Msg::Sort(match select.value().as_str() {
+                "newest" => BoardSort::Newest,
+                "name" => BoardSort::Name,
+                _ => BoardSort::Oldest,
+            })
+        });
+        let option = |value: &'static str, sort: BoardSort, label: &'static str| {
+            html! { <option {value} selected={self.sort == sort}>{label}</option> }
+        };
+        html! {
+          <div class="grid">
+            <input type="search" placeholder="Search" value={self.query.clone()} {oninput} />
+            <select {onchange}>
+              { option("oldest", BoardSort::Oldest, "Oldest first") }
+              { option("newest", BoardSort::Newest, "Newest first") }
+              { option("name", BoardSort::Name, "By name") }
+            </select>
+          </div>
+        }
+    }
     fn view_new_board_form(&self, link: &Scope<Self>) -> Html {
         let name_ref = self.create_form_name_ref.clone();
         let onclick = link.callback(move |_e: MouseEvent| {
@@ -106,6 +402,13 @@ impl BoardsList {
             let name = name_input.value();
             Msg::CreateBoard(name)
         });
+        let onchange = link.batch_callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = input.files().and_then(|files| files.get(0));
+            // Picking the same file again imports it again
+            input.set_value("");
+            file.map(Msg::ImportBoard)
+        });
         html! {
           <article>
             <h3>{"Create new board"}</h3>
@@ -114,7 +417,17 @@ impl BoardsList {
               <input ref={self.create_form_name_ref.clone()} id="name" type="text" name="name" /><br/>
               <input {onclick} type="submit" value="Create" />
             </form>
+            <label for="import">{"Or import a board exported from another instance"}</label>
+            <input id="import" type="file" accept=".json,application/json" {onchange} />
           </article>
         }
     }
 }
+
+async fn read_archive(file: &File) -> Result<BoardArchive, String> {
+    let text = JsFuture::from(file.text())
+        .await
+        .map_err(|_| "Could not read the file".to_string())?;
+    let text = text.as_string().unwrap_or_default();
+    serde_json::from_str(&text).map_err(|e| format!("Not a board archive: {}", e))
+}
@@ -0,0 +1,74 @@
+use super::board::Board;
+use crate::api::{fetch_invite, APIError};
+use shared::datatypes::InviteTarget;
+use yew::prelude::*;
+
+// Finds the board an invite link leads to, then shows it like any other
+pub struct InviteView {
+    target: Option<InviteTarget>,
+    error: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct InviteProps {
+    pub token: String,
+    #[prop_or_default]
+    pub embed: bool,
+}
+
+pub enum Msg {
+    Loaded(InviteTarget),
+    APIError(APIError),
+}
+
+impl Component for InviteView {
+    type Message = Msg;
+    type Properties = InviteProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let token = ctx.props().token.clone();
+        ctx.link().send_future(async move {
+            match fetch_invite(&token).await {
+                Ok(target) => Msg::Loaded(target),
+                Err(err) => Msg::APIError(err),
+            }
+        });
+        Self {
+            target: None,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Loaded(target) => {
+                log::info!(
+                    "Invited to board {} as {}",
+                    target.board_id,
+                    target.role.as_str()
+                );
+                self.target = Some(target);
+            }
+            Msg::APIError(err) => {
+                self.error = Some(match err.code.as_str() {
+                    "not_found" => "This invite link is invalid or has expired".to_string(),
+                    _ => err.message,
+                });
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        match (&self.target, &self.error) {
+            (Some(target), _) => html! {
+                <Board id={target.board_id} invite={Some(ctx.props().token.clone())}
+                    embed={ctx.props().embed} />
+            },
+            (None, Some(error)) => html! {
+                <main class="container"><article role="alert">{ error }</article></main>
+            },
+            (None, None) => html! { <main class="container" aria-busy="true"></main> },
+        }
+    }
+}
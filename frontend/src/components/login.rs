@@ -0,0 +1,121 @@
+use crate::api::{login, register, APIError};
+use crate::identity::Identity;
+use crate::routes::Route;
+use shared::datatypes::{Credentials, User};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::{History as _, RouterScopeExt};
+
+pub struct Login {
+    loading: bool,
+    // Last error, shown until the next attempt
+    error: Option<String>,
+    username_ref: NodeRef,
+    password_ref: NodeRef,
+}
+
+#[derive(Debug)]
+pub enum Msg {
+    SignIn,
+    Register,
+    SignedIn(User),
+    APIError(APIError),
+}
+
+impl Component for Login {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            loading: false,
+            error: None,
+            username_ref: NodeRef::default(),
+            password_ref: NodeRef::default(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::SignIn | Msg::Register => {
+                let credentials = self.credentials();
+                let registering = matches!(msg, Msg::Register);
+                if registering {
+                    if let Err(message) = credentials.validate() {
+                        self.error = Some(message);
+                        return true;
+                    }
+                }
+                self.loading = true;
+                ctx.link().send_future(async move {
+                    let result = match registering {
+                        true => register(&credentials).await,
+                        false => login(&credentials).await,
+                    };
+                    match result {
+                        Ok(user) => Msg::SignedIn(user),
+                        Err(err) => Msg::APIError(err),
+                    }
+                });
+                true
+            }
+            Msg::SignedIn(user) => {
+                log::info!("Signed in as {}", user.username);
+                Identity::set_name(&user.username);
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::BoardsList);
+                }
+                false
+            }
+            Msg::APIError(err) => {
+                self.loading = false;
+                self.error = Some(err.message);
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let onsubmit = ctx.link().callback(|e: FocusEvent| {
+            e.prevent_default();
+            Msg::SignIn
+        });
+        let register = ctx.link().callback(|e: MouseEvent| {
+            e.prevent_default();
+            Msg::Register
+        });
+        html! {
+          <main class="container">
+            <article>
+              <h3>{"Sign in"}</h3>
+              { match &self.error {
+                  Some(message) => html! { <p role="alert">{message}</p> },
+                  None => html! {},
+              } }
+              <form {onsubmit}>
+                <label for="username">{"Username"}</label>
+                <input ref={self.username_ref.clone()} id="username" type="text"
+                  autocomplete="username" required=true />
+                <label for="password">{"Password"}</label>
+                <input ref={self.password_ref.clone()} id="password" type="password"
+                  autocomplete="current-password" required=true />
+                <div class="grid">
+                  <input type="submit" value="Sign in" aria-busy={self.loading.to_string()} />
+                  <button class="secondary" onclick={register}>{"Create account"}</button>
+                </div>
+              </form>
+            </article>
+          </main>
+        }
+    }
+}
+
+impl Login {
+    fn credentials(&self) -> Credentials {
+        let value = |node: &NodeRef| node.cast::<HtmlInputElement>().unwrap().value();
+        Credentials {
+            username: value(&self.username_ref).trim().to_string(),
+            password: value(&self.password_ref),
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod board;
 pub mod boards_list;
+pub mod invite;
+pub mod login;
+mod outbox;
 mod ws_client;
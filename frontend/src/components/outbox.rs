@@ -0,0 +1,85 @@
+// Shape operations the server hasn't acknowledged yet, see ServerMessage::Ack. They are sent
+// again in order after a reconnection, as they may have been lost with the connection, with the
+// same ids so the server skips those it already applied. Mirrored to localStorage so they survive
+// a page reload
+use crate::utils::{local_storage, new_operation_id};
+use shared::datatypes::{Operation, SocketMessage};
+
+pub struct Outbox {
+    key: String,
+    operations: Vec<Operation>,
+}
+
+impl Outbox {
+    pub fn load(board_id: i32) -> Outbox {
+        let key = format!("liveboard.outbox.{}", board_id);
+        let operations = local_storage()
+            .and_then(|storage| storage.get_item(&key).ok().flatten())
+            .and_then(|json| match serde_json::from_str(&json) {
+                Ok(operations) => Some(operations),
+                Err(e) => match serde_json::from_str::<Vec<SocketMessage>>(&json) {
+                    // Saved before operations had ids
+                    Ok(messages) => Some(messages.into_iter().map(new_operation).collect()),
+                    Err(_) => {
+                        log::warn!("Discarding invalid outbox {:?}: {}", key, e);
+                        None
+                    }
+                },
+            })
+            .unwrap_or_default();
+        Outbox { key, operations }
+    }
+
+    // Returns the operation to send
+    pub fn push(&mut self, m: SocketMessage) -> Operation {
+        let op = new_operation(m);
+        self.operations.push(op.clone());
+        self.save();
+        op
+    }
+
+    // Drops the oldest operation, which the server answered
+    pub fn acknowledge(&mut self) {
+        if self.operations.is_empty() {
+            log::warn!("Acknowledgement without pending operations");
+            return;
+        }
+        self.operations.remove(0);
+        self.save();
+    }
+
+    // In the order they were pushed
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    fn save(&self) {
+        let storage = match local_storage() {
+            Some(storage) => storage,
+            None => return,
+        };
+        let result = if self.operations.is_empty() {
+            storage.remove_item(&self.key)
+        } else {
+            storage.set_item(&self.key, &serde_json::to_string(&self.operations).unwrap())
+        };
+        if let Err(e) = result {
+            log::warn!("Error saving outbox: {:?}", e);
+        }
+    }
+}
+
+fn new_operation(m: SocketMessage) -> Operation {
+    Operation {
+        id: new_operation_id(),
+        message: Box::new(m),
+    }
+}
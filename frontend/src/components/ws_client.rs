@@ -1,55 +1,95 @@
-use futures::channel::mpsc::UnboundedSender;
-use futures::{future, pin_mut};
-use futures::{SinkExt, StreamExt};
-use gloo_net::websocket::{futures::WebSocket, Message as WsMessage};
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures::future::{self, poll_fn};
+use futures::{stream, SinkExt, StreamExt};
+use gloo_net::websocket::{futures::WebSocket, Message as WsMessage, State, WebSocketError};
+use gloo_timers::future::TimeoutFuture;
 
 type WsSender = UnboundedSender<WsMessage>;
 
+// Reconnection backoff: INITIAL_BACKOFF_MS * 2^attempt, capped to MAX_BACKOFF_MS
+const INITIAL_BACKOFF_MS: u32 = 500;
+const MAX_BACKOFF_MS: u32 = 30_000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    // Waiting before the next connection attempt
+    Disconnected { retry_in_ms: u32 },
+}
+
 pub struct WSClient {
     pub sender: WsSender,
 }
 
-pub fn new_ws_client<F>(board_id: i32, handle_message: F) -> WSClient
+enum Event {
+    Received(Result<WsMessage, WebSocketError>),
+    Send(WsMessage),
+    Closed,
+    // The WSClient and all its clones were dropped
+    Dropped,
+}
+
+// Opens a websocket to the board and keeps it open, reconnecting with exponential backoff when
+// the connection is lost. Messages sent while disconnected are dropped, see Board's outbox for
+// those that matter
+pub fn new_ws_client<F, S>(
+    board_id: i32,
+    invite: Option<&str>,
+    // Tells the server not to let us draw, see Board's embed prop
+    embed: bool,
+    handle_message: F,
+    handle_state: S,
+) -> WSClient
 where
     F: 'static + Fn(WsMessage),
+    S: 'static + Fn(ConnectionState),
 {
     let window = web_sys::window().unwrap();
     let location = window.location();
-    let url = format!(
+    let mut url = format!(
         "ws://{}:{}/api/boards/{}/ws",
         location.hostname().unwrap(),
         location.port().unwrap(),
         board_id,
     );
+    if let Some(token) = invite {
+        url = format!("{}?invite={}", url, token);
+    }
+    if embed {
+        let separator = if invite.is_some() { '&' } else { '?' };
+        url = format!("{}{}embed=true", url, separator);
+    }
     log::info!("url= {:?}", url);
-    let ws = WebSocket::open(&url).unwrap();
-    let (write, mut read) = ws.split();
 
-    let (mut read_tx, mut read_rx) = futures::channel::mpsc::unbounded();
-    let (write_tx, write_rx) = futures::channel::mpsc::unbounded();
-
-    let fwd_writes = write_rx.map(Ok).forward(write);
-    let fwd_reads = async move {
-        while let Some(m) = read.next().await {
-            read_tx.send(m).await.unwrap()
-        }
-    };
-    wasm_bindgen_futures::spawn_local(async move {
-        pin_mut!(fwd_writes, fwd_reads);
-        future::select(fwd_writes, fwd_reads).await;
-    });
+    let (write_tx, mut write_rx) = futures::channel::mpsc::unbounded();
 
     wasm_bindgen_futures::spawn_local(async move {
-        while let Some(m) = read_rx.next().await {
-            match m {
-                Ok(WsMessage::Text(value)) => {
-                    handle_message(WsMessage::Text(value));
+        let mut attempt = 0;
+        loop {
+            handle_state(ConnectionState::Connecting);
+            match connect(&url).await {
+                Some(ws) => {
+                    attempt = 0;
+                    handle_state(ConnectionState::Connected);
+                    if !run_session(ws, &mut write_rx, &handle_message).await {
+                        // The WSClient and all its clones were dropped
+                        return;
+                    }
                 }
-                Ok(WsMessage::Bytes(value)) => {
-                    handle_message(WsMessage::Bytes(value));
-                }
-                Err(err) => {
-                    log::info!("Error: {}", err);
+                None => log::info!("Connection to {} failed", url),
+            }
+            let retry_in_ms = MAX_BACKOFF_MS.min(INITIAL_BACKOFF_MS << attempt.min(16));
+            attempt += 1;
+            handle_state(ConnectionState::Disconnected { retry_in_ms });
+            TimeoutFuture::new(retry_in_ms).await;
+            loop {
+                match write_rx.try_next() {
+                    Ok(Some(m)) => log::warn!("Dropping message sent while disconnected: {:?}", m),
+                    // Nobody is left to use the connection, e.g. the board was left
+                    Ok(None) => return,
+                    // Nothing more queued
+                    Err(_) => break,
                 }
             }
         }
@@ -58,6 +98,63 @@ where
     WSClient { sender: write_tx }
 }
 
+// Resolves once the socket is open, None if it failed to open
+async fn connect(url: &str) -> Option<WebSocket> {
+    let mut ws = WebSocket::open(url).ok()?;
+    // The sink becomes ready once the socket leaves the connecting state
+    poll_fn(|cx| ws.poll_ready_unpin(cx)).await.ok()?;
+    match ws.state() {
+        State::Open => Some(ws),
+        _ => None,
+    }
+}
+
+// Forwards messages in both directions until the socket is closed. Returns false if there is
+// nobody left to send messages
+async fn run_session<F>(
+    ws: WebSocket,
+    write_rx: &mut UnboundedReceiver<WsMessage>,
+    handle_message: &F,
+) -> bool
+where
+    F: Fn(WsMessage),
+{
+    let (mut write, read) = ws.split();
+    let incoming = read
+        .map(Event::Received)
+        .chain(stream::once(future::ready(Event::Closed)));
+    let outgoing = write_rx
+        .by_ref()
+        .map(Event::Send)
+        .chain(stream::once(future::ready(Event::Dropped)));
+    let mut events = stream::select(incoming, outgoing);
+    while let Some(event) = events.next().await {
+        match event {
+            Event::Received(Ok(m)) => handle_message(m),
+            Event::Received(Err(err)) => log::info!("Error: {}", err),
+            Event::Send(m) => {
+                if let Err(err) = write.send(m).await {
+                    // Reconnect rather than carry on with messages missing
+                    log::warn!("Error sending message, reconnecting: {}", err);
+                    return true;
+                }
+            }
+            Event::Closed => {
+                log::info!("Connection closed");
+                return true;
+            }
+            Event::Dropped => {
+                // Closes the socket, so the server sees us leave
+                if let Err(err) = write.close().await {
+                    log::info!("Error closing connection: {}", err);
+                }
+                return false;
+            }
+        }
+    }
+    false
+}
+
 impl WSClient {}
 
 impl Clone for WSClient {
@@ -0,0 +1,76 @@
+// Canvas rendering of the shared shape model, see shared::render
+use shared::datatypes::{Point, Shape};
+use shared::render::{Primitive, Style};
+use std::f64;
+use wasm_bindgen::JsValue;
+use web_sys::CanvasRenderingContext2d;
+
+pub fn draw_shape(context: &CanvasRenderingContext2d, shape: &Shape) {
+    let Style {
+        color,
+        stroke_width,
+    } = shape.style();
+    let color = JsValue::from_str(&color.hex_color());
+    context.set_fill_style(&color);
+    context.set_stroke_style(&color);
+    context.set_line_width(stroke_width);
+    for primitive in shape.primitives() {
+        draw_primitive(context, &primitive);
+    }
+}
+
+fn draw_primitive(context: &CanvasRenderingContext2d, primitive: &Primitive) {
+    match primitive {
+        Primitive::Disc { center, radius } => {
+            context.begin_path();
+            context
+                .arc(center.x, center.y, *radius, 0.0, f64::consts::PI * 2.0)
+                .unwrap();
+            context.fill();
+        }
+        Primitive::Rect {
+            corner,
+            width,
+            height,
+        } => {
+            // Sharp corners, like SVG's default
+            context.set_line_join("miter");
+            context.stroke_rect(corner.x, corner.y, *width, *height);
+        }
+        Primitive::Ellipse {
+            center,
+            radius_x,
+            radius_y,
+        } => {
+            context.begin_path();
+            context
+                .ellipse(
+                    center.x,
+                    center.y,
+                    *radius_x,
+                    *radius_y,
+                    0.0,
+                    0.0,
+                    f64::consts::PI * 2.0,
+                )
+                .unwrap();
+            context.stroke();
+        }
+        Primitive::Polyline(points) => draw_polyline(context, points),
+    }
+}
+
+fn draw_polyline(context: &CanvasRenderingContext2d, points: &[Point]) {
+    let (first, rest) = match points.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    context.set_line_cap("round");
+    context.set_line_join("round");
+    context.begin_path();
+    context.move_to(first.x, first.y);
+    for point in rest {
+        context.line_to(point.x, point.y);
+    }
+    context.stroke();
+}
@@ -0,0 +1,66 @@
+// Who we are to the other people on a board. Kept in localStorage so our cursor keeps its name
+// and color across reloads. The server replaces the id with one bound to our session
+use crate::utils::{local_storage, random_bytes};
+use serde::{Deserialize, Serialize};
+use shared::datatypes::{Color, Participant};
+
+const STORAGE_KEY: &str = "liveboard.identity";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Identity {
+    pub id: String,
+    pub name: String,
+    pub color: Color,
+}
+
+impl Identity {
+    pub fn load() -> Identity {
+        let stored = local_storage()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+            .and_then(|json| serde_json::from_str(&json).ok());
+        match stored {
+            Some(identity) => identity,
+            None => {
+                let identity = Identity::new();
+                identity.save();
+                identity
+            }
+        }
+    }
+
+    // Signed in users are named after their account
+    pub fn set_name(name: &str) {
+        let mut identity = Identity::load();
+        identity.name = name.to_string();
+        identity.save();
+    }
+
+    pub fn participant(&self) -> Participant {
+        Participant {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            color: self.color,
+        }
+    }
+
+    fn new() -> Identity {
+        let id: String = random_bytes::<8>()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let [r, g, b] = random_bytes();
+        Identity {
+            name: format!("Guest {}", &id[..4]),
+            id,
+            color: Color { r, g, b },
+        }
+    }
+
+    fn save(&self) {
+        if let Some(storage) = local_storage() {
+            if let Err(e) = storage.set_item(STORAGE_KEY, &serde_json::to_string(self).unwrap()) {
+                log::warn!("Error saving identity: {:?}", e);
+            }
+        }
+    }
+}
@@ -68,6 +68,7 @@ struct Animation {
 }
 
 pub struct LiveCursor {
+    pub name: String,
     pub color: Color,
     pub current_position: Point,
     performance: web_sys::Performance,
@@ -83,6 +84,7 @@ impl LiveCursor {
     pub fn new(pos: PointerPosition) -> LiveCursor {
         let performance = get_performance();
         LiveCursor {
+            name: pos.name,
             color: pos.color,
             current_position: (pos.x, pos.y),
             last_add_point: performance.now(),
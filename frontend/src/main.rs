@@ -1,8 +1,12 @@
 mod components;
 use crate::components::board::Board;
 use crate::components::boards_list::BoardsList;
+use crate::components::invite::InviteView;
+use crate::components::login::Login;
 use yew_router::prelude::{BrowserRouter, Redirect, Switch};
 mod api;
+mod drawing;
+mod identity;
 mod live_cursor;
 mod routes;
 mod utils;
@@ -17,6 +21,11 @@ fn switch(route: &Route) -> Html {
                 <Redirect<Route> to={Route::BoardsList} />
             }
         }
+        Route::Login => {
+            return html! {
+                <Login />
+            }
+        }
         Route::BoardsList => {
             return html! {
                 <BoardsList />
@@ -27,6 +36,16 @@ fn switch(route: &Route) -> Html {
                 <Board id={id.to_owned()} />
             }
         }
+        Route::Invite { token } => {
+            return html! {
+                <InviteView token={token.to_owned()} />
+            }
+        }
+        Route::Embed { token } => {
+            return html! {
+                <InviteView token={token.to_owned()} embed=true />
+            }
+        }
     }
 }
 
@@ -4,8 +4,16 @@ use yew_router::prelude::*;
 pub enum Route {
     #[at("/")]
     Home,
+    #[at("/login")]
+    Login,
     #[at("/boards")]
     BoardsList,
     #[at("/boards/:id")]
     BoardView { id: i32 },
+    // A board opened with an invite link
+    #[at("/invites/:token")]
+    Invite { token: String },
+    // The same, as a bare view-only board for other sites to show in an iframe
+    #[at("/embed/:token")]
+    Embed { token: String },
 }
@@ -1,3 +1,4 @@
+use shared::datatypes::{OperationId, ShapeId, Uuid};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
@@ -14,8 +15,36 @@ pub fn set_interval(f: &Closure<dyn FnMut()>, interval: i32) {
         .expect("should register `setInterval` OK");
 }
 
+pub fn local_storage() -> Option<web_sys::Storage> {
+    window().local_storage().ok().flatten()
+}
+
 pub fn performance() -> web_sys::Performance {
     window()
         .performance()
         .expect("window.performance should be available")
 }
+
+// Filled from the browser's CSPRNG
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    window()
+        .crypto()
+        .expect("window.crypto should be available")
+        .get_random_values_with_u8_array(&mut bytes)
+        .unwrap();
+    bytes
+}
+
+// Random (v4) UUID
+fn new_uuid() -> Uuid {
+    uuid::Builder::from_random_bytes(random_bytes()).into_uuid()
+}
+
+pub fn new_shape_id() -> ShapeId {
+    new_uuid()
+}
+
+pub fn new_operation_id() -> OperationId {
+    new_uuid()
+}
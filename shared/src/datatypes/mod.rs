@@ -1,26 +1,118 @@
+pub use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+pub use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SocketMessage {
-    Circle(Circle),
+    ShapeCreated(ShapeEntry),
+    ShapeUpdated(ShapeEntry),
+    ShapeDeleted(ShapeId),
+    Pointer(PointerPosition),
+    // Introduces the client to the others on the board, once connected
+    Hello(Participant),
+    // A shape operation sent by the client, see Operation
+    Operation(Operation),
+}
+
+// Clients send their shape operations again after a reconnection, as they may have been lost with
+// the connection. The id they generate tells the server which ones it already applied
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: OperationId,
+    // ShapeCreated, ShapeUpdated or ShapeDeleted
+    pub message: Box<SocketMessage>,
+}
+
+pub type OperationId = Uuid;
+
+// Sent by the server to the clients connected to a board
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Event(BoardEvent),
     Pointer(PointerPosition),
+    // The board was deleted, the server closes the socket right after
+    BoardDeleted,
+    // The client's last message was refused, e.g. a drawing from a viewer
+    Rejected(String),
+    // The client's access to the board changed, e.g. its invite was revoked. The server closes the
+    // socket right after, and reconnecting applies the new access
+    AccessChanged(String),
+    // The client's last shape operation was handled, after the Event it produced if any. Every
+    // operation gets either this or Rejected, in the order they were sent
+    Ack,
+    // Everyone else on the board, sent in answer to Hello
+    PresenceSnapshot(Vec<Participant>),
+    Joined(Participant),
+    // The participant with this id closed its last connection to the board
+    Left(String),
+}
+
+// A persisted operation on a board. `seq` is assigned by the server and increases by one with
+// every operation on the board, so clients can tell whether they missed one
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardEvent {
+    pub seq: i64,
+    pub message: SocketMessage,
+}
+
+// The shapes of a board, and the sequence number of the last event they may not include. Applying
+// the events after it on top brings them up to date
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardShapes {
+    pub shapes: Vec<ShapeEntry>,
+    pub last_seq: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PointerPosition {
+    // Set by the server to the sender's session, so others can tell cursors apart and nobody can
+    // move someone else's. Whatever the client sends is overwritten
     pub id: String,
+    // Shown next to the cursor. The server replaces it with the username of signed in users
+    pub name: String,
     pub x: f64,
     pub y: f64,
     pub color: Color,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Someone connected to a board. Same id and name as their pointer
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Participant {
+    pub id: String,
+    pub name: String,
+    pub color: Color,
+}
+
+// Generated by the client that creates the shape, so it can refer to it before the server has
+// persisted it
+pub type ShapeId = Uuid;
+
+// A shape as stored on a board. Serialized as the shape's own fields plus `id`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShapeEntry {
+    pub id: ShapeId,
+    #[serde(flatten)]
+    pub shape: Shape,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Shape {
     Circle(Circle),
+    Rectangle(Rectangle),
+    Ellipse(Ellipse),
+    Line(Line),
+    Arrow(Arrow),
+    Stroke(Stroke),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Circle {
     pub x: f64,
     pub y: f64,
@@ -39,7 +131,72 @@ impl Clone for Circle {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+// (x, y) is the top-left corner
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rectangle {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub stroke_width: f64,
+    pub color: Color,
+}
+
+// (x, y) is the center
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ellipse {
+    pub x: f64,
+    pub y: f64,
+    pub radius_x: f64,
+    pub radius_y: f64,
+    pub stroke_width: f64,
+    pub color: Color,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Line {
+    pub start: Point,
+    pub end: Point,
+    pub stroke_width: f64,
+    pub color: Color,
+}
+
+// A line with an arrow head at `end`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Arrow {
+    pub start: Point,
+    pub end: Point,
+    pub stroke_width: f64,
+    pub color: Color,
+}
+
+const ARROW_HEAD_LENGTH: f64 = 12.0;
+const ARROW_HEAD_ANGLE: f64 = std::f64::consts::PI / 6.0;
+
+impl Arrow {
+    // The two ends of the arrow head "barbs", to be joined with `end`
+    pub fn head(&self) -> [Point; 2] {
+        let angle = (self.end.y - self.start.y).atan2(self.end.x - self.start.x);
+        let barb = |a: f64| Point {
+            x: self.end.x - ARROW_HEAD_LENGTH * a.cos(),
+            y: self.end.y - ARROW_HEAD_LENGTH * a.sin(),
+        };
+        [
+            barb(angle - ARROW_HEAD_ANGLE),
+            barb(angle + ARROW_HEAD_ANGLE),
+        ]
+    }
+}
+
+// Freehand polyline
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+    pub points: Vec<Point>,
+    pub stroke_width: f64,
+    pub color: Color,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -48,17 +205,317 @@ pub struct Color {
 
 impl Color {
     pub fn hex_color(&self) -> String {
-        return format!("#{}", hex::encode([self.r, self.g, self.b]));
+        format!("#{}", hex::encode([self.r, self.g, self.b]))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Beyond this, coordinates and sizes can't be drawn sensibly
+pub const MAX_SHAPE_COORDINATE: f64 = 1e9;
+pub const MAX_STROKE_POINTS: usize = 100_000;
+
+impl Shape {
+    // Shapes that don't come from our own frontend, like those of imported archives, are checked
+    // before being stored
+    pub fn validate(&self) -> Result<(), String> {
+        let (coordinates, sizes) = match self {
+            Shape::Circle(c) => (vec![c.x, c.y], vec![c.radius]),
+            Shape::Rectangle(r) => (vec![r.x, r.y], vec![r.width, r.height, r.stroke_width]),
+            Shape::Ellipse(e) => (vec![e.x, e.y], vec![e.radius_x, e.radius_y, e.stroke_width]),
+            Shape::Line(l) => (
+                vec![l.start.x, l.start.y, l.end.x, l.end.y],
+                vec![l.stroke_width],
+            ),
+            Shape::Arrow(a) => (
+                vec![a.start.x, a.start.y, a.end.x, a.end.y],
+                vec![a.stroke_width],
+            ),
+            Shape::Stroke(s) => {
+                if s.points.is_empty() || s.points.len() > MAX_STROKE_POINTS {
+                    return Err(format!(
+                        "Strokes must have between 1 and {} points",
+                        MAX_STROKE_POINTS
+                    ));
+                }
+                (
+                    s.points.iter().flat_map(|p| [p.x, p.y]).collect(),
+                    vec![s.stroke_width],
+                )
+            }
+        };
+        if coordinates
+            .iter()
+            .any(|v| !v.is_finite() || v.abs() > MAX_SHAPE_COORDINATE)
+        {
+            return Err(format!(
+                "Coordinates must be between -{} and {}",
+                MAX_SHAPE_COORDINATE, MAX_SHAPE_COORDINATE
+            ));
+        }
+        if sizes
+            .iter()
+            .any(|v| !v.is_finite() || *v < 0.0 || *v > MAX_SHAPE_COORDINATE)
+        {
+            return Err(format!(
+                "Sizes must be between 0 and {}",
+                MAX_SHAPE_COORDINATE
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Board {
     pub name: String,
     pub id: i32,
+    // Deleted, but can still be restored
+    pub archived: bool,
+    // UTC, like every date
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardDetails {
+    #[serde(flatten)]
+    pub board: Board,
+    pub shape_count: i64,
+    // Number of operations ever made on the board
+    pub event_count: i64,
+    // Time of the last operation, or of the creation if there was none
+    pub last_modified: NaiveDateTime,
+    // Of the user asking
+    pub role: Role,
+}
+
+// What a user may do on a board. Each role can do everything the previous ones can
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // Sees the board and the other users' pointers
+    Viewer,
+    // Draws
+    Editor,
+    // Renames, deletes and shares the board. Every board has exactly one
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+}
+
+// Body of POST /auth/register and POST /auth/login
+#[derive(Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+pub const MAX_USERNAME_LENGTH: usize = 32;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+impl Credentials {
+    // Only checked on registration, so that rules can change without locking anyone out
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.';
+        if self.username.is_empty()
+            || self.username.len() > MAX_USERNAME_LENGTH
+            || !self.username.chars().all(valid_char)
+        {
+            return Err(format!(
+                "Usernames are 1 to {} letters, digits, '.', '-' or '_'",
+                MAX_USERNAME_LENGTH
+            ));
+        }
+        if self.password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(format!(
+                "Passwords must be at least {} characters long",
+                MIN_PASSWORD_LENGTH
+            ));
+        }
+        Ok(())
+    }
+}
+
+// A user who can access a board
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub username: String,
+    pub role: Role,
+}
+
+// Body of PUT /boards/{id}/members/{username}
+#[derive(Serialize, Deserialize)]
+pub struct SetMemberRequest {
+    pub role: Role,
+}
+
+// A share link, `/invites/{token}` in the frontend
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Invite {
+    pub id: i32,
+    pub token: String,
+    pub role: Role,
+    pub created_at: NaiveDateTime,
+    // Never expires if None
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+pub const MAX_INVITE_HOURS: i64 = 24 * 365;
+
+// Body of POST /boards/{id}/invites
+#[derive(Serialize, Deserialize)]
+pub struct CreateInviteRequest {
+    pub role: Role,
+    pub expires_in_hours: Option<i64>,
+}
+
+impl CreateInviteRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.role == Role::Owner {
+            return Err("Invites can't make owners".to_string());
+        }
+        match self.expires_in_hours {
+            Some(hours) if !(1..=MAX_INVITE_HOURS).contains(&hours) => Err(format!(
+                "Invites expire after 1 to {} hours",
+                MAX_INVITE_HOURS
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// What GET /invites/{token} tells about the board it opens
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InviteTarget {
+    pub board_id: i32,
+    pub board_name: String,
+    pub role: Role,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CreateBoardRequest {
     pub name: String,
 }
+
+// Order of the boards listed by GET /boards
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoardSort {
+    #[default]
+    Oldest,
+    Newest,
+    Name,
+}
+
+// Boards listed when no limit is given, and the most that can be asked for
+pub const DEFAULT_BOARD_PAGE_SIZE: i64 = 50;
+pub const MAX_BOARD_PAGE_SIZE: i64 = 100;
+
+// One page of GET /boards. Pass `next_cursor` back as `cursor` to get the following one, it is
+// missing on the last page
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardPage {
+    pub boards: Vec<Board>,
+    pub next_cursor: Option<String>,
+}
+
+// Fields left out are unchanged
+#[derive(Serialize, Deserialize, Default)]
+pub struct UpdateBoardRequest {
+    pub name: Option<String>,
+}
+
+impl UpdateBoardRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.name {
+            Some(name) => validate_board_name(name),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CreateBoardRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_board_name(&self.name)
+    }
+}
+
+pub const MAX_BOARD_NAME_LENGTH: usize = 100;
+
+// Checked by the frontend before sending, and by the backend which has the last word
+pub fn validate_board_name(name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Board name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_BOARD_NAME_LENGTH {
+        return Err(format!(
+            "Board name cannot be longer than {} characters",
+            MAX_BOARD_NAME_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+// Bumped whenever BoardArchive changes in a way older backends can't read
+pub const BOARD_ARCHIVE_VERSION: u32 = 1;
+
+// Portable copy of a board, to move it between instances. Returned by GET /boards/{id}/export
+// and accepted by POST /boards/import
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardArchive {
+    pub version: u32,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    // In drawing order. Their ids are not kept on import
+    pub shapes: Vec<ShapeEntry>,
+}
+
+impl BoardArchive {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version != BOARD_ARCHIVE_VERSION {
+            return Err(format!(
+                "Unsupported archive version {}, expected {}",
+                self.version, BOARD_ARCHIVE_VERSION
+            ));
+        }
+        validate_board_name(&self.name)?;
+        for (i, entry) in self.shapes.iter().enumerate() {
+            entry
+                .shape
+                .validate()
+                .map_err(|message| format!("Shape {}: {}", i, message))?;
+        }
+        Ok(())
+    }
+}
+
+// Body of every error response of the API
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    // Stable and machine readable, e.g. `not_found`
+    pub code: String,
+    // For humans
+    pub message: String,
+}
@@ -0,0 +1,262 @@
+// Geometry helpers on the shape model, shared so that every client agrees on what is hit or moved
+use crate::datatypes::{Point, Shape};
+
+const MIN_SCALE: f64 = 0.05;
+const MAX_SCALE: f64 = 20.0;
+
+impl Point {
+    pub fn distance(&self, other: &Point) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+
+    pub fn translated(&self, dx: f64, dy: f64) -> Point {
+        Point {
+            x: self.x + dx,
+            y: self.y + dy,
+        }
+    }
+}
+
+// Distance from `p` to the segment [a, b]
+fn distance_to_segment(p: &Point, a: &Point, b: &Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_sq = dx * dx + dy * dy;
+    if length_sq == 0.0 {
+        return p.distance(a);
+    }
+    let t = (((p.x - a.x) * dx + (p.y - a.y) * dy) / length_sq).clamp(0.0, 1.0);
+    p.distance(&Point {
+        x: a.x + t * dx,
+        y: a.y + t * dy,
+    })
+}
+
+fn distance_to_polyline(p: &Point, points: &[Point]) -> f64 {
+    match points {
+        [] => f64::INFINITY,
+        [single] => p.distance(single),
+        _ => points
+            .windows(2)
+            .map(|w| distance_to_segment(p, &w[0], &w[1]))
+            .fold(f64::INFINITY, f64::min),
+    }
+}
+
+impl Shape {
+    // Whether `p` hits the shape, allowing `tolerance` units of slack. Rectangles and ellipses
+    // are hit anywhere inside their outline
+    pub fn contains(&self, p: &Point, tolerance: f64) -> bool {
+        match self {
+            Shape::Circle(c) => p.distance(&Point { x: c.x, y: c.y }) <= c.radius + tolerance,
+            Shape::Rectangle(r) => {
+                p.x >= r.x - tolerance
+                    && p.x <= r.x + r.width + tolerance
+                    && p.y >= r.y - tolerance
+                    && p.y <= r.y + r.height + tolerance
+            }
+            Shape::Ellipse(e) => {
+                let nx = (p.x - e.x) / (e.radius_x + tolerance);
+                let ny = (p.y - e.y) / (e.radius_y + tolerance);
+                nx * nx + ny * ny <= 1.0
+            }
+            Shape::Line(l) => {
+                distance_to_segment(p, &l.start, &l.end) <= l.stroke_width / 2.0 + tolerance
+            }
+            Shape::Arrow(a) => {
+                distance_to_segment(p, &a.start, &a.end) <= a.stroke_width / 2.0 + tolerance
+            }
+            Shape::Stroke(s) => {
+                distance_to_polyline(p, &s.points) <= s.stroke_width / 2.0 + tolerance
+            }
+        }
+    }
+
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        match self {
+            Shape::Circle(c) => {
+                c.x += dx;
+                c.y += dy;
+            }
+            Shape::Rectangle(r) => {
+                r.x += dx;
+                r.y += dy;
+            }
+            Shape::Ellipse(e) => {
+                e.x += dx;
+                e.y += dy;
+            }
+            Shape::Line(l) => {
+                l.start = l.start.translated(dx, dy);
+                l.end = l.end.translated(dx, dy);
+            }
+            Shape::Arrow(a) => {
+                a.start = a.start.translated(dx, dy);
+                a.end = a.end.translated(dx, dy);
+            }
+            Shape::Stroke(s) => {
+                for point in s.points.iter_mut() {
+                    *point = point.translated(dx, dy);
+                }
+            }
+        }
+    }
+}
+
+// Axis-aligned bounding box, in world coordinates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Bounds {
+    fn around(p: &Point, margin: f64) -> Bounds {
+        Bounds {
+            min: p.translated(-margin, -margin),
+            max: p.translated(margin, margin),
+        }
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: Point {
+                x: self.min.x.min(other.min.x),
+                y: self.min.y.min(other.min.y),
+            },
+            max: Point {
+                x: self.max.x.max(other.max.x),
+                y: self.max.y.max(other.max.y),
+            },
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
+
+    // Bounds of all the given shapes, None if there are none
+    pub fn of_shapes<'a>(shapes: impl IntoIterator<Item = &'a Shape>) -> Option<Bounds> {
+        shapes
+            .into_iter()
+            .map(|s| s.bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+fn bounds_of_points(points: &[Point], margin: f64) -> Bounds {
+    points
+        .iter()
+        .map(|p| Bounds::around(p, margin))
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or(Bounds {
+            min: Point { x: 0.0, y: 0.0 },
+            max: Point { x: 0.0, y: 0.0 },
+        })
+}
+
+impl Shape {
+    // Bounding box including the stroke width
+    pub fn bounds(&self) -> Bounds {
+        match self {
+            Shape::Circle(c) => Bounds::around(&Point { x: c.x, y: c.y }, c.radius),
+            Shape::Rectangle(r) => {
+                let half = r.stroke_width / 2.0;
+                Bounds {
+                    min: Point { x: r.x, y: r.y }.translated(-half, -half),
+                    max: Point {
+                        x: r.x + r.width,
+                        y: r.y + r.height,
+                    }
+                    .translated(half, half),
+                }
+            }
+            Shape::Ellipse(e) => {
+                let half = e.stroke_width / 2.0;
+                Bounds {
+                    min: Point {
+                        x: e.x - e.radius_x - half,
+                        y: e.y - e.radius_y - half,
+                    },
+                    max: Point {
+                        x: e.x + e.radius_x + half,
+                        y: e.y + e.radius_y + half,
+                    },
+                }
+            }
+            Shape::Line(l) => bounds_of_points(&[l.start, l.end], l.stroke_width / 2.0),
+            Shape::Arrow(a) => {
+                let [left, right] = a.head();
+                bounds_of_points(&[a.start, a.end, left, right], a.stroke_width / 2.0)
+            }
+            Shape::Stroke(s) => bounds_of_points(&s.points, s.stroke_width / 2.0),
+        }
+    }
+}
+
+// Maps the infinite world coordinate system, in which shapes and pointers are expressed, to
+// screen pixels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    // World coordinates of the screen's top-left corner
+    pub origin: Point,
+    // Screen pixels per world unit
+    pub scale: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Viewport {
+        Viewport {
+            origin: Point { x: 0.0, y: 0.0 },
+            scale: 1.0,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn to_world(&self, screen: &Point) -> Point {
+        Point {
+            x: self.origin.x + screen.x / self.scale,
+            y: self.origin.y + screen.y / self.scale,
+        }
+    }
+
+    pub fn to_screen(&self, world: &Point) -> Point {
+        Point {
+            x: (world.x - self.origin.x) * self.scale,
+            y: (world.y - self.origin.y) * self.scale,
+        }
+    }
+
+    // Moves the view by a screen-space delta
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.origin = self.origin.translated(-dx / self.scale, -dy / self.scale);
+    }
+
+    // Multiplies the scale by `factor`, keeping the world point under `screen` in place
+    pub fn zoom_at(&mut self, screen: &Point, factor: f64) {
+        let anchor = self.to_world(screen);
+        self.scale = (self.scale * factor).clamp(MIN_SCALE, MAX_SCALE);
+        self.origin = anchor.translated(-screen.x / self.scale, -screen.y / self.scale);
+    }
+
+    // The viewport showing all of `bounds`, centered, on a screen of the given size
+    pub fn fit(bounds: &Bounds, width: f64, height: f64, margin: f64) -> Viewport {
+        let available_width = (width - 2.0 * margin).max(1.0);
+        let available_height = (height - 2.0 * margin).max(1.0);
+        let scale = (available_width / bounds.width().max(1.0))
+            .min(available_height / bounds.height().max(1.0))
+            .clamp(MIN_SCALE, MAX_SCALE);
+        let center = Point {
+            x: (bounds.min.x + bounds.max.x) / 2.0,
+            y: (bounds.min.y + bounds.max.y) / 2.0,
+        };
+        Viewport {
+            origin: center.translated(-width / 2.0 / scale, -height / 2.0 / scale),
+            scale,
+        }
+    }
+}
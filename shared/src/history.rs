@@ -0,0 +1,107 @@
+// Per-user undo/redo, expressed as operations over the shared shape model. Undoing applies the
+// inverse of an operation, so only the shapes touched by that operation are affected and changes
+// made by other users in the meantime are kept
+use crate::datatypes::{ShapeEntry, ShapeId, SocketMessage};
+
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone, Debug)]
+pub enum ShapeOperation {
+    Create(ShapeEntry),
+    Update {
+        before: ShapeEntry,
+        after: ShapeEntry,
+    },
+    Delete(ShapeEntry),
+}
+
+impl ShapeOperation {
+    pub fn shape_id(&self) -> ShapeId {
+        match self {
+            ShapeOperation::Create(entry) => entry.id,
+            ShapeOperation::Update { after, .. } => after.id,
+            ShapeOperation::Delete(entry) => entry.id,
+        }
+    }
+
+    pub fn inverse(&self) -> ShapeOperation {
+        match self {
+            ShapeOperation::Create(entry) => ShapeOperation::Delete(entry.clone()),
+            ShapeOperation::Update { before, after } => ShapeOperation::Update {
+                before: after.clone(),
+                after: before.clone(),
+            },
+            ShapeOperation::Delete(entry) => ShapeOperation::Create(entry.clone()),
+        }
+    }
+
+    // Whether the operation still makes sense given whether its shape is currently on the board
+    pub fn applies_to(&self, shape_exists: bool) -> bool {
+        match self {
+            ShapeOperation::Create(_) => !shape_exists,
+            ShapeOperation::Update { .. } | ShapeOperation::Delete(_) => shape_exists,
+        }
+    }
+
+    // The socket message that performs this operation
+    pub fn to_message(&self) -> SocketMessage {
+        match self {
+            ShapeOperation::Create(entry) => SocketMessage::ShapeCreated(entry.clone()),
+            ShapeOperation::Update { after, .. } => SocketMessage::ShapeUpdated(after.clone()),
+            ShapeOperation::Delete(entry) => SocketMessage::ShapeDeleted(entry.id),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<ShapeOperation>,
+    redo_stack: Vec<ShapeOperation>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    // Records an operation we just performed. This discards the redo stack
+    pub fn record(&mut self, op: ShapeOperation) {
+        self.undo_stack.push(op);
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    // Returns the operation to apply to undo our last operation. Operations made moot by other
+    // users (e.g. they deleted the shape we moved) are skipped
+    pub fn undo(&mut self, shape_exists: impl Fn(ShapeId) -> bool) -> Option<ShapeOperation> {
+        while let Some(op) = self.undo_stack.pop() {
+            let inverse = op.inverse();
+            if inverse.applies_to(shape_exists(inverse.shape_id())) {
+                self.redo_stack.push(op);
+                return Some(inverse);
+            }
+        }
+        None
+    }
+
+    // Returns the operation to apply to redo our last undone operation
+    pub fn redo(&mut self, shape_exists: impl Fn(ShapeId) -> bool) -> Option<ShapeOperation> {
+        while let Some(op) = self.redo_stack.pop() {
+            if op.applies_to(shape_exists(op.shape_id())) {
+                self.undo_stack.push(op.clone());
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
@@ -1 +1,4 @@
 pub mod datatypes;
+pub mod geometry;
+pub mod history;
+pub mod render;
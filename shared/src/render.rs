@@ -0,0 +1,147 @@
+// What shapes look like, as a few primitives that both the canvas and the SVG export draw, so
+// that a board looks the same wherever it is shown
+use crate::datatypes::{Color, Point, Shape};
+use crate::geometry::Bounds;
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Primitive {
+    // Filled circle
+    Disc {
+        center: Point,
+        radius: f64,
+    },
+    // The other primitives are outlines, `stroke_width` wide
+    Rect {
+        corner: Point,
+        width: f64,
+        height: f64,
+    },
+    Ellipse {
+        center: Point,
+        radius_x: f64,
+        radius_y: f64,
+    },
+    // With round caps and joins
+    Polyline(Vec<Point>),
+}
+
+// Shared by all the primitives of a shape
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Style {
+    pub color: Color,
+    pub stroke_width: f64,
+}
+
+impl Shape {
+    pub fn style(&self) -> Style {
+        let (color, stroke_width) = match self {
+            Shape::Circle(c) => (c.color, 0.0),
+            Shape::Rectangle(r) => (r.color, r.stroke_width),
+            Shape::Ellipse(e) => (e.color, e.stroke_width),
+            Shape::Line(l) => (l.color, l.stroke_width),
+            Shape::Arrow(a) => (a.color, a.stroke_width),
+            Shape::Stroke(s) => (s.color, s.stroke_width),
+        };
+        Style {
+            color,
+            stroke_width,
+        }
+    }
+
+    // In drawing order
+    pub fn primitives(&self) -> Vec<Primitive> {
+        match self {
+            Shape::Circle(c) => vec![Primitive::Disc {
+                center: Point { x: c.x, y: c.y },
+                radius: c.radius,
+            }],
+            Shape::Rectangle(r) => vec![Primitive::Rect {
+                corner: Point { x: r.x, y: r.y },
+                width: r.width,
+                height: r.height,
+            }],
+            Shape::Ellipse(e) => vec![Primitive::Ellipse {
+                center: Point { x: e.x, y: e.y },
+                radius_x: e.radius_x,
+                radius_y: e.radius_y,
+            }],
+            Shape::Line(l) => vec![Primitive::Polyline(vec![l.start, l.end])],
+            Shape::Arrow(a) => {
+                let [left, right] = a.head();
+                vec![
+                    Primitive::Polyline(vec![a.start, a.end]),
+                    Primitive::Polyline(vec![left, a.end, right]),
+                ]
+            }
+            Shape::Stroke(s) => vec![Primitive::Polyline(s.points.clone())],
+        }
+    }
+}
+
+// A standalone SVG document showing `shapes`, later ones on top, cropped to their bounds plus
+// `margin` world units on every side
+pub fn to_svg(shapes: &[Shape], margin: f64) -> String {
+    let origin = Point { x: 0.0, y: 0.0 };
+    let bounds = Bounds::of_shapes(shapes).unwrap_or(Bounds {
+        min: origin,
+        max: origin,
+    });
+    let (x, y) = (bounds.min.x - margin, bounds.min.y - margin);
+    let (width, height) = (
+        bounds.width() + 2.0 * margin,
+        bounds.height() + 2.0 * margin,
+    );
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"{}\" height=\"{}\">\n",
+        x, y, width, height, width, height
+    );
+    for shape in shapes {
+        let style = shape.style();
+        let color = style.color.hex_color();
+        let outline = format!(
+            "fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"",
+            color, style.stroke_width
+        );
+        for primitive in shape.primitives() {
+            // Writing to a String can't fail
+            let _ = match primitive {
+                Primitive::Disc { center, radius } => writeln!(
+                    svg,
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                    center.x, center.y, radius, color
+                ),
+                Primitive::Rect {
+                    corner,
+                    width,
+                    height,
+                } => writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                    corner.x, corner.y, width, height, outline
+                ),
+                Primitive::Ellipse {
+                    center,
+                    radius_x,
+                    radius_y,
+                } => writeln!(
+                    svg,
+                    "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" {}/>",
+                    center.x, center.y, radius_x, radius_y, outline
+                ),
+                Primitive::Polyline(points) => {
+                    let points: Vec<String> =
+                        points.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+                    writeln!(
+                        svg,
+                        "<polyline points=\"{}\" {} stroke-linecap=\"round\" stroke-linejoin=\"round\"/>",
+                        points.join(" "),
+                        outline
+                    )
+                }
+            };
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
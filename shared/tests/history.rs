@@ -0,0 +1,118 @@
+use shared::datatypes::{Circle, Color, Shape, ShapeEntry, ShapeId, SocketMessage, Uuid};
+use shared::history::{History, ShapeOperation};
+use std::collections::HashMap;
+
+fn entry(id: u128, x: f64) -> ShapeEntry {
+    ShapeEntry {
+        id: Uuid::from_u128(id),
+        shape: Shape::Circle(Circle {
+            x,
+            y: 0.0,
+            radius: 5.0,
+            color: Color { r: 0, g: 0, b: 0 },
+        }),
+    }
+}
+
+// Minimal board applying operations the way clients do
+#[derive(Default)]
+struct Board {
+    shapes: HashMap<ShapeId, ShapeEntry>,
+}
+
+impl Board {
+    fn apply(&mut self, op: &ShapeOperation) {
+        match op.to_message() {
+            SocketMessage::ShapeCreated(e) | SocketMessage::ShapeUpdated(e) => {
+                self.shapes.insert(e.id, e);
+            }
+            SocketMessage::ShapeDeleted(id) => {
+                self.shapes.remove(&id);
+            }
+            SocketMessage::Pointer(_) | SocketMessage::Hello(_) | SocketMessage::Operation(_) => {}
+        }
+    }
+
+    fn x(&self, id: u128) -> Option<f64> {
+        match self.shapes.get(&Uuid::from_u128(id))?.shape {
+            Shape::Circle(ref c) => Some(c.x),
+            _ => None,
+        }
+    }
+}
+
+fn undo(history: &mut History, board: &mut Board) {
+    let op = history.undo(|id| board.shapes.contains_key(&id)).unwrap();
+    board.apply(&op);
+}
+
+#[test]
+fn undo_only_reverts_own_operations() {
+    let mut board = Board::default();
+    let mut mine = History::new();
+
+    let op = ShapeOperation::Create(entry(1, 0.0));
+    board.apply(&op);
+    mine.record(op);
+    // Someone else draws after us
+    board.apply(&ShapeOperation::Create(entry(2, 0.0)));
+
+    undo(&mut mine, &mut board);
+    assert_eq!(board.x(1), None);
+    assert_eq!(board.x(2), Some(0.0));
+
+    let op = mine.redo(|id| board.shapes.contains_key(&id)).unwrap();
+    board.apply(&op);
+    assert_eq!(board.x(1), Some(0.0));
+}
+
+#[test]
+fn undo_update_restores_previous_state() {
+    let mut board = Board::default();
+    let mut mine = History::new();
+    board.apply(&ShapeOperation::Create(entry(1, 0.0)));
+
+    let op = ShapeOperation::Update {
+        before: entry(1, 0.0),
+        after: entry(1, 10.0),
+    };
+    board.apply(&op);
+    mine.record(op);
+    assert_eq!(board.x(1), Some(10.0));
+
+    undo(&mut mine, &mut board);
+    assert_eq!(board.x(1), Some(0.0));
+}
+
+#[test]
+fn undo_skips_operations_made_moot_by_others() {
+    let mut board = Board::default();
+    let mut mine = History::new();
+
+    let op = ShapeOperation::Create(entry(1, 0.0));
+    board.apply(&op);
+    mine.record(op);
+    let op = ShapeOperation::Create(entry(2, 0.0));
+    board.apply(&op);
+    mine.record(op);
+    // Someone else erases our second shape
+    board.apply(&ShapeOperation::Delete(entry(2, 0.0)));
+
+    undo(&mut mine, &mut board);
+    assert_eq!(board.x(1), None);
+    assert!(!mine.can_undo());
+}
+
+#[test]
+fn recording_clears_redo() {
+    let mut board = Board::default();
+    let mut mine = History::new();
+    let op = ShapeOperation::Create(entry(1, 0.0));
+    board.apply(&op);
+    mine.record(op);
+    undo(&mut mine, &mut board);
+    assert!(mine.can_redo());
+
+    mine.record(ShapeOperation::Create(entry(3, 0.0)));
+    assert!(!mine.can_redo());
+}
@@ -0,0 +1,34 @@
+use shared::datatypes::{BoardEvent, Color, PointerPosition, ServerMessage, SocketMessage, Uuid};
+
+#[test]
+fn board_event_carries_sequence_number_next_to_message() {
+    let event = ServerMessage::Event(BoardEvent {
+        seq: 7,
+        message: SocketMessage::ShapeDeleted(Uuid::from_u128(42)),
+    });
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["Event"]["seq"], 7);
+    assert_eq!(
+        json["Event"]["message"]["ShapeDeleted"],
+        "00000000-0000-0000-0000-00000000002a"
+    );
+}
+
+#[test]
+fn server_message_round_trips() {
+    let pointer = ServerMessage::Pointer(PointerPosition {
+        id: "a".to_string(),
+        name: "Alice".to_string(),
+        x: 1.0,
+        y: 2.0,
+        color: Color { r: 1, g: 2, b: 3 },
+    });
+    let json = serde_json::to_string(&pointer).unwrap();
+    match serde_json::from_str::<ServerMessage>(&json).unwrap() {
+        ServerMessage::Pointer(p) => assert_eq!(
+            (p.id.as_str(), p.name.as_str(), p.x, p.y),
+            ("a", "Alice", 1.0, 2.0)
+        ),
+        m => panic!("unexpected {:?}", m),
+    }
+}
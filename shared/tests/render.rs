@@ -0,0 +1,51 @@
+use shared::datatypes::{Arrow, Circle, Color, Point, Rectangle, Shape};
+use shared::render::{to_svg, Primitive};
+
+const RED: Color = Color { r: 255, g: 0, b: 0 };
+
+#[test]
+fn arrows_are_drawn_with_their_head() {
+    let arrow = Arrow {
+        start: Point { x: 0.0, y: 0.0 },
+        end: Point { x: 100.0, y: 0.0 },
+        stroke_width: 2.0,
+        color: RED,
+    };
+    let [left, right] = arrow.head();
+    assert_eq!(
+        Shape::Arrow(arrow.clone()).primitives(),
+        vec![
+            Primitive::Polyline(vec![arrow.start, arrow.end]),
+            Primitive::Polyline(vec![left, arrow.end, right]),
+        ]
+    );
+}
+
+#[test]
+fn svg_is_cropped_to_the_shapes() {
+    let shapes = vec![
+        Shape::Circle(Circle {
+            x: 10.0,
+            y: 20.0,
+            radius: 5.0,
+            color: RED,
+        }),
+        Shape::Rectangle(Rectangle {
+            x: 50.0,
+            y: 50.0,
+            width: 20.0,
+            height: 10.0,
+            stroke_width: 2.0,
+            color: Color { r: 0, g: 0, b: 255 },
+        }),
+    ];
+    let svg = to_svg(&shapes, 10.0);
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-5 5 86 66\""));
+    let circle = svg.find("<circle cx=\"10\" cy=\"20\" r=\"5\" fill=\"#ff0000\"/>");
+    let rect = svg.find(
+        "<rect x=\"50\" y=\"50\" width=\"20\" height=\"10\" fill=\"none\" stroke=\"#0000ff\" stroke-width=\"2\"/>",
+    );
+    // Later shapes are drawn on top
+    assert!(circle.unwrap() < rect.unwrap());
+    assert!(svg.ends_with("</svg>\n"));
+}
@@ -0,0 +1,87 @@
+use shared::datatypes::{Circle, Color, Line, Point, Rectangle, Shape, ShapeEntry, Uuid};
+
+const COLOR: Color = Color { r: 0, g: 0, b: 0 };
+
+fn rectangle() -> Shape {
+    Shape::Rectangle(Rectangle {
+        x: 10.0,
+        y: 10.0,
+        width: 20.0,
+        height: 10.0,
+        stroke_width: 2.0,
+        color: COLOR,
+    })
+}
+
+#[test]
+fn shape_entry_serializes_id_next_to_shape_fields() {
+    let entry = ShapeEntry {
+        id: Uuid::from_u128(42),
+        shape: Shape::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            radius: 5.0,
+            color: COLOR,
+        }),
+    };
+    let json = serde_json::to_value(&entry).unwrap();
+    assert_eq!(json["type"], "Circle");
+    assert_eq!(json["id"], "00000000-0000-0000-0000-00000000002a");
+    assert_eq!(json["radius"], 5.0);
+
+    let parsed: ShapeEntry = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed.id, entry.id);
+    assert!(matches!(parsed.shape, Shape::Circle(c) if c.radius == 5.0));
+}
+
+#[test]
+fn rectangle_is_hit_inside_and_within_tolerance() {
+    let shape = rectangle();
+    assert!(shape.contains(&Point { x: 15.0, y: 15.0 }, 0.0));
+    assert!(shape.contains(&Point { x: 32.0, y: 15.0 }, 3.0));
+    assert!(!shape.contains(&Point { x: 32.0, y: 15.0 }, 0.0));
+}
+
+#[test]
+fn line_is_hit_near_segment_only() {
+    let shape = Shape::Line(Line {
+        start: Point { x: 0.0, y: 0.0 },
+        end: Point { x: 100.0, y: 0.0 },
+        stroke_width: 2.0,
+        color: COLOR,
+    });
+    assert!(shape.contains(&Point { x: 50.0, y: 1.0 }, 0.0));
+    assert!(!shape.contains(&Point { x: 50.0, y: 10.0 }, 0.0));
+    // Past the end of the segment
+    assert!(!shape.contains(&Point { x: 110.0, y: 0.0 }, 5.0));
+}
+
+#[test]
+fn translate_moves_shape() {
+    let mut shape = rectangle();
+    shape.translate(5.0, -5.0);
+    assert!(shape.contains(&Point { x: 34.0, y: 6.0 }, 0.0));
+    assert!(!shape.contains(&Point { x: 12.0, y: 12.0 }, 0.0));
+}
+
+#[test]
+fn invalid_shapes_are_rejected() {
+    assert!(rectangle().validate().is_ok());
+    let negative = Shape::Circle(Circle {
+        x: 0.0,
+        y: 0.0,
+        radius: -1.0,
+        color: COLOR,
+    });
+    assert!(negative.validate().is_err());
+    let far = Shape::Line(Line {
+        start: Point { x: 0.0, y: 0.0 },
+        end: Point {
+            x: f64::NAN,
+            y: 0.0,
+        },
+        stroke_width: 1.0,
+        color: COLOR,
+    });
+    assert!(far.validate().is_err());
+}
@@ -0,0 +1,74 @@
+use shared::datatypes::{Circle, Color, Point, Rectangle, Shape};
+use shared::geometry::{Bounds, Viewport};
+
+fn assert_close(a: &Point, b: &Point) {
+    assert!(
+        (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn world_and_screen_round_trip() {
+    let mut viewport = Viewport::default();
+    viewport.pan(100.0, -50.0);
+    viewport.zoom_at(&Point { x: 30.0, y: 40.0 }, 2.5);
+    let world = Point { x: 12.0, y: -7.0 };
+    assert_close(&viewport.to_world(&viewport.to_screen(&world)), &world);
+}
+
+#[test]
+fn zoom_keeps_anchor_in_place() {
+    let mut viewport = Viewport::default();
+    let anchor = Point { x: 200.0, y: 100.0 };
+    let before = viewport.to_world(&anchor);
+    viewport.zoom_at(&anchor, 3.0);
+    assert_close(&viewport.to_world(&anchor), &before);
+    assert_eq!(viewport.scale, 3.0);
+}
+
+#[test]
+fn fit_shows_all_shapes() {
+    let color = Color { r: 0, g: 0, b: 0 };
+    let shapes = vec![
+        Shape::Circle(Circle {
+            x: -500.0,
+            y: 0.0,
+            radius: 10.0,
+            color,
+        }),
+        Shape::Rectangle(Rectangle {
+            x: 1000.0,
+            y: 300.0,
+            width: 100.0,
+            height: 50.0,
+            stroke_width: 2.0,
+            color,
+        }),
+    ];
+    let bounds = Bounds::of_shapes(&shapes).unwrap();
+    assert_eq!(bounds.min.x, -510.0);
+    assert_eq!(bounds.max.y, 351.0);
+
+    let viewport = Viewport::fit(&bounds, 800.0, 600.0, 0.0);
+    for corner in [bounds.min, bounds.max] {
+        let screen = viewport.to_screen(&corner);
+        assert!(
+            screen.x >= -1e-9 && screen.x <= 800.0 + 1e-9,
+            "{:?}",
+            screen
+        );
+        assert!(
+            screen.y >= -1e-9 && screen.y <= 600.0 + 1e-9,
+            "{:?}",
+            screen
+        );
+    }
+}
+
+#[test]
+fn no_bounds_without_shapes() {
+    assert_eq!(Bounds::of_shapes(&[]), None);
+}